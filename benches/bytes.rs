@@ -0,0 +1,50 @@
+#![feature(test)]
+
+extern crate test;
+
+use {
+	test::Bencher,
+	utl::bytes::{BytesSer, BytesDe, Buffered},
+	std::io::BufReader,
+};
+
+type T = Vec<(u16, u32, u64, i128, Option<usize>)>;
+
+fn data() -> Vec<u8> {
+	let value: T = (0..1 << 16u32)
+		.map(|i| {
+			let n = (i as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> (i % 64);
+			(n as _, (n >> 16) as _, n, -(n as i128) << (i % 32), Some(i as _).filter(|i| i % 3 == 0))
+		})
+		.collect();
+
+	let mut ser: BytesSer = BytesSer::new();
+	ser.serialize(&value).unwrap();
+	ser.bytes().collect()
+}
+
+fn bench(b: &mut Bencher, de: impl Fn(&[u8]) -> T) {
+	let data = data();
+	b.bytes = data.len() as u64;
+	b.iter(|| de(&data));
+}
+
+#[bench]
+fn de_slice(b: &mut Bencher) {
+	bench(b, |mut data| BytesDe::new(&mut data).deserialize().unwrap())
+}
+
+#[bench]
+fn de_slice_buffered(b: &mut Bencher) {
+	bench(b, |data| BytesDe::new(&mut Buffered(data)).deserialize().unwrap())
+}
+
+#[bench]
+fn de_buf_reader(b: &mut Bencher) {
+	bench(b, |data| BytesDe::new(&mut BufReader::new(data)).deserialize().unwrap())
+}
+
+#[bench]
+fn de_buf_reader_buffered(b: &mut Bencher) {
+	bench(b, |data| BytesDe::new(&mut Buffered(BufReader::new(data))).deserialize().unwrap())
+}
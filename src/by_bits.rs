@@ -1,6 +1,6 @@
 use std::{cmp::Ordering, hash::{Hash, Hasher}};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub struct ByBits<T>(pub T);
 
 impl<T> From<T> for ByBits<T> {
@@ -43,4 +43,16 @@ impl_by_bits!{f32}
 impl_by_bits!{f64}
 impl_by_bits!{::half::f16}
 
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for ByBits<T> {
+	fn serialize<S: serde::Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+		self.0.serialize(ser)
+	}
+}
 
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for ByBits<T> {
+	fn deserialize<D: serde::Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+		T::deserialize(de).map(Self)
+	}
+}
@@ -60,25 +60,44 @@ impl<K, V> DerefMut for ByKey<K, V> {
 	fn deref_mut(&mut self) -> &mut V { &mut self.value }
 }
 
-/*
 #[cfg(feature = "serde")]
-impl<K: serde::Serialize, V> serde::Serialize for ByKey<K, V> {
+impl<K: serde::Serialize, V: serde::Serialize> serde::Serialize for ByKey<K, V> {
 	fn serialize<S: serde::Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
-		self.key.serialize(ser)
+		serde::Serialize::serialize(&(&self.key, &self.value), ser)
 	}
 }
 
 #[cfg(feature = "serde")]
 impl<'de, K, V> serde::Deserialize<'de> for ByKey<K, V> where
 	K: serde::Deserialize<'de>,
-	V: Default,
+	V: serde::Deserialize<'de>,
 {
 	fn deserialize<D: serde::Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
-		Ok(Self {
+		let (key, value) = serde::Deserialize::deserialize(de)?;
+		Ok(Self { key, value })
+	}
+}
+
+// for `#[serde(with = "utl::by_key::key_only")]`, leaving `value` to its default
+#[cfg(feature = "serde")]
+pub mod key_only {
+	use super::ByKey;
+
+	pub fn serialize<K, V, S>(v: &ByKey<K, V>, ser: S) -> Result<S::Ok, S::Error> where
+		K: serde::Serialize,
+		S: serde::Serializer,
+	{
+		serde::Serialize::serialize(&v.key, ser)
+	}
+
+	pub fn deserialize<'de, K, V, D>(de: D) -> Result<ByKey<K, V>, D::Error> where
+		K: serde::Deserialize<'de>,
+		V: Default,
+		D: serde::Deserializer<'de>,
+	{
+		Ok(ByKey {
 			key: serde::Deserialize::deserialize(de)?,
 			value: <_>::default(),
 		})
 	}
 }
-*/
-
@@ -8,8 +8,9 @@ use {
 		},
 	},
 	std::{
-		io::Read,
+		io::{self, Read, BufRead},
 		fmt::{self, Display},
+		marker::PhantomData,
 	}
 };
 
@@ -21,6 +22,11 @@ pub enum Error {
 	Custom(String),
 	AllocExceeded,
 	Utf8(std::str::Utf8Error),
+	InvalidRecord,
+	Ser(super::ser::Error),
+	DepthExceeded,
+	UnknownTypeId(u32),
+	VarintOverflow,
 }
 
 impl Display for Error {
@@ -34,6 +40,11 @@ impl Display for Error {
 			Self::Custom(e) => write!(f, "{}", e),
 			Self::AllocExceeded => write!(f, "ran out of allocation"),
 			Self::Utf8(e) => write!(f, "UTF-8 decoding error: {}", e),
+			Self::InvalidRecord => write!(f, "torn or corrupted log record"),
+			Self::Ser(e) => write!(f, "{}", e),
+			Self::DepthExceeded => write!(f, "exceeded the nesting depth limit"),
+			Self::UnknownTypeId(id) => write!(f, "unknown type ID {}", id),
+			Self::VarintOverflow => write!(f, "length doesn't fit in a usize"),
 		}
 	}
 }
@@ -44,7 +55,8 @@ impl serde::de::Error for Error {
 
 impl std::error::Error for Error {}
 
-fn eof<T>() -> Result<T> {
+#[cold]
+pub(super) fn eof<T>() -> Result<T> {
 	#[derive(Debug)]
 	struct Eof;
 
@@ -63,66 +75,116 @@ fn eof<T>() -> Result<T> {
 
 pub type Result<T = ()> = std::result::Result<T, Error>;
 
-pub struct BytesDe<'de, R> { read: &'de mut R, alloc: usize }
+// wrapping a `BufRead`er, like a `BufReader`, lets `BytesDe` decode varints
+// straight out of its buffer rather than making a `Read::read` call per byte,
+// which `benches/bytes.rs` measures at about a fifth faster; slices always get
+// this, so wrapping them changes nothing
+pub struct Buffered<R: ?Sized + BufRead>(pub R);
 
-pub struct BytesDeLen<'a, 'de, R> { len: usize, de: &'a mut BytesDe<'de, R> }
+impl<R: ?Sized + BufRead> Read for Buffered<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { self.0.read(buf) }
+	fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> { self.0.read_exact(buf) }
+}
 
-impl<'de, R: Read> BytesDe<'de, R> {
-	pub fn with_alloc_limit(read: &'de mut R, limit: usize) -> Self {
-		Self { read, alloc: limit }
-	}
+impl<R: ?Sized + BufRead> BufRead for Buffered<R> {
+	fn fill_buf(&mut self) -> io::Result<&[u8]> { self.0.fill_buf() }
+	fn consume(&mut self, n: usize) { self.0.consume(n); }
+}
 
-	pub fn new(read: &'de mut R) -> Self { Self::with_alloc_limit(read, 1 << 24) }
+trait Buffer: Read {
+	fn buffer(&mut self) -> Option<io::Result<&[u8]>>;
+	fn consume(&mut self, n: usize);
+}
 
-	pub fn end(self) -> &'de mut R { self.read }
+impl<R: ?Sized + Read> Buffer for R {
+	default fn buffer(&mut self) -> Option<io::Result<&[u8]>> { None }
+	default fn consume(&mut self, _n: usize) {}
+}
 
-	pub fn deserialize<T: serde::Deserialize<'de>>(&mut self) -> Result<T> {
-		T::deserialize(self)
-	}
+// the bound on `Buffered` is what lets this specialize
+impl<R: ?Sized + BufRead> Buffer for Buffered<R> {
+	fn buffer(&mut self) -> Option<io::Result<&[u8]>> { Some(self.0.fill_buf()) }
+	fn consume(&mut self, n: usize) { self.0.consume(n); }
+}
+
+impl Buffer for &[u8] {
+	fn buffer(&mut self) -> Option<io::Result<&[u8]>> { Some(Ok(self)) }
+	fn consume(&mut self, n: usize) { *self = &self[n ..]; }
+}
+
+// where the varint decoders take their bytes from
+trait Source {
+	fn byte(&mut self) -> Result<u8>;
+	fn rex(&mut self, to: &mut [u8]) -> Result;
+}
 
+impl Source for &[u8] {
+	#[inline]
 	fn byte(&mut self) -> Result<u8> {
-		let mut byte = [0u8];
-		match self.read.read(&mut byte) {
-			Ok(0) => eof(),
-			Err(e) => Err(Error::Io(e)),
-			_ => Ok(byte[0]),
+		match self.split_first() {
+			Some((&byte, rest)) => { *self = rest; Ok(byte) },
+			None => eof(),
 		}
 	}
 
+	#[inline]
 	fn rex(&mut self, to: &mut [u8]) -> Result {
-		if !to.is_empty() { self.read.read_exact(to).map_err(Error::Io)?; }
+		if self.len() < to.len() { return eof(); }
+		let (head, rest) = self.split_at(to.len());
+		to.copy_from_slice(head);
+		*self = rest;
 		Ok(())
 	}
+}
 
-	fn consume_alloc(&mut self, n: usize) -> Result {
-		if n > self.alloc {
-			Err(Error::AllocExceeded)
-		} else {
-			self.alloc -= n;
-			Ok(())
-		}
-	}
+impl<R: ?Sized + Read> Source for BytesDe<'_, R> {
+	#[inline]
+	fn byte(&mut self) -> Result<u8> { BytesDe::byte(self) }
+	#[inline]
+	fn rex(&mut self, to: &mut [u8]) -> Result { BytesDe::rex(self, to) }
+}
 
-	fn de_u16(&mut self) -> Result<u16> {
-		Ok(match self.byte()? {
+trait Varint: Sized {
+	// the longest encoding
+	const MAX: usize;
+	fn decode(src: &mut impl Source) -> Result<Self>;
+}
+
+// a length prefix, which unlike a `usize` value is LEB128
+struct Len(usize);
+
+impl Varint for u16 {
+	const MAX: usize = 3;
+
+	#[inline(always)]
+	fn decode(src: &mut impl Source) -> Result<Self> {
+		Ok(match src.byte()? {
 			n@0..=0x7F => n as u16,
-			0x80 => match self.byte()? {
+			0x80 => match src.byte()? {
 				n@..=0x7F => n as u16 | 0x80,
-				n@0x80..  => ((n as u16) << 8) | self.byte()? as u16,
+				n@0x80..  => ((n as u16) << 8) | src.byte()? as u16,
 			},
-			n => ((n as u16 & 0x7F) << 8) | self.byte()? as u16,
+			n => ((n as u16 & 0x7F) << 8) | src.byte()? as u16,
 		})
 	}
+}
 
-	fn de_usize(&mut self) -> Result<usize> {
-		let mut n: u64 = 0;
+impl Varint for Len {
+	const MAX: usize = 10;
+
+	#[inline(always)]
+	fn decode(src: &mut impl Source) -> Result<Self> {
+		// a `u64` takes at most 10 bytes, whose groups fit in a `u128` with
+		// their carries
+		let mut n: u128 = 0;
 		let mut bits = 0;
 		loop {
-			let byte = self.byte()?;
+			let byte = src.byte()?;
 
-			n |= ((byte & 0x7F) as u64) << bits;
+			n += ((byte & 0x7F) as u128) << bits;
 
 			if byte >> 7 == 1 {
+				if bits == 63 { return Err(Error::VarintOverflow); }
 				n += 0x80 << bits;
 				bits += 7;
 			} else {
@@ -130,39 +192,25 @@ impl<'de, R: Read> BytesDe<'de, R> {
 			}
 		}
 
-		Ok(n as usize)
-	}
-
-	fn de_usize_alloc(&mut self) -> Result<usize> {
-		let len = self.de_usize()?;
-		self.consume_alloc(len)?;
-		Ok(len)
-	}
-
-	fn de_usize_buf(&mut self) -> Result<Vec<u8>> {
-		let mut buf = vec![0u8; self.de_usize_alloc()?];
-		self.rex(&mut buf)?;
-		Ok(buf)
+		if n > usize::max_value() as u128 { return Err(Error::VarintOverflow); }
+		Ok(Len(n as usize))
 	}
+}
 
-	fn de_bool(&mut self) -> Result<bool> {
-		Ok(match self.byte()? {
-			0 => false,
-			1 => true,
-			n => return Err(Error::InvalidBool(n)),
-		})
-	}
+impl Varint for u32 {
+	const MAX: usize = 5;
 
-	fn de_u32(&mut self) -> Result<u32> {
-		let head = self.byte()?;
+	#[inline(always)]
+	fn decode(src: &mut impl Source) -> Result<Self> {
+		let head = src.byte()?;
 		if head >> 6 == 0 {
 			Ok(head as _)
 		} else if head & 0b111111 == 0 {
-			let next = self.byte()?;
+			let next = src.byte()?;
 			let not_shift = head >> 7;
 			let extra = ((head | (next >> 2)) >> (4 + not_shift)) & 0b11;
 			let mut bytes = [0u8; 4];
-			self.rex(&mut bytes[4 - extra as usize ..])?;
+			src.rex(&mut bytes[4 - extra as usize ..])?;
 			Ok(
 				u32::from_be_bytes(bytes)
 				| (
@@ -173,22 +221,26 @@ impl<'de, R: Read> BytesDe<'de, R> {
 		} else {
 			let extra = head >> 6;
 			let mut bytes = [0u8; 4];
-			dbg!(extra);
-			self.rex(&mut bytes[4 - extra as usize ..])?;
+			src.rex(&mut bytes[4 - extra as usize ..])?;
 			Ok(u32::from_be_bytes(bytes) | ((head as u32 & 0b111111) << (extra * 8)))
 		}
 	}
+}
+
+impl Varint for u64 {
+	const MAX: usize = 9;
 
-	fn de_u64(&mut self) -> Result<u64> {
-		let head = self.byte()?;
+	#[inline(always)]
+	fn decode(src: &mut impl Source) -> Result<Self> {
+		let head = src.byte()?;
 		if head >> 5 == 0 {
 			Ok(head as _)
 		} else if head & 0b11111 == 0 {
-			let next = self.byte()?;
+			let next = src.byte()?;
 			let shift = head.leading_zeros();
 			let extra = ((head | (next >> 3)) >> (4 - shift)) & 0b111;
 			let mut bytes = [0u8; 8];
-			self.rex(&mut bytes[8 - extra as usize ..])?;
+			src.rex(&mut bytes[8 - extra as usize ..])?;
 			Ok(
 				u64::from_be_bytes(bytes)
 				| (
@@ -198,21 +250,26 @@ impl<'de, R: Read> BytesDe<'de, R> {
 		} else {
 			let extra = head >> 5;
 			let mut bytes = [0u8; 8];
-			self.rex(&mut bytes[8 - extra as usize ..])?;
+			src.rex(&mut bytes[8 - extra as usize ..])?;
 			Ok(u64::from_be_bytes(bytes) | ((head as u64 & 0b11111) << (extra * 8)))
 		}
 	}
+}
 
-	fn de_u128(&mut self) -> Result<u128> {
-		let head = self.byte()?;
+impl Varint for u128 {
+	const MAX: usize = 17;
+
+	#[inline(always)]
+	fn decode(src: &mut impl Source) -> Result<Self> {
+		let head = src.byte()?;
 		if head >> 4 == 0 {
 			Ok(head as _)
 		} else if head & 0b1111 == 0 {
-			let next = self.byte()?;
+			let next = src.byte()?;
 			let shift = head.leading_zeros();
 			let extra = ((head | (next >> 4)) >> (3 - shift)) & 0b1111;
 			let mut bytes = [0u8; 16];
-			self.rex(&mut bytes[16 - extra as usize ..])?;
+			src.rex(&mut bytes[16 - extra as usize ..])?;
 			Ok(
 				u128::from_be_bytes(bytes)
 				| (
@@ -222,12 +279,288 @@ impl<'de, R: Read> BytesDe<'de, R> {
 		} else {
 			let extra = head >> 4;
 			let mut bytes = [0u8; 16];
-			self.rex(&mut bytes[16 - extra as usize ..])?;
+			src.rex(&mut bytes[16 - extra as usize ..])?;
 			Ok(u128::from_be_bytes(bytes) | ((head as u128 & 0b1111) << (extra * 8)))
 		}
 	}
 }
 
+pub struct BytesDe<'de, R: ?Sized> {
+	read: &'de mut R,
+	alloc: usize,
+	depth: usize,
+	consumed: usize,
+	mode: Mode,
+	ahead: [u8; 64],
+	ahead_pos: u8,
+	ahead_len: u8,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode { Build, Validate, Skip }
+
+pub struct BytesDeLen<'a, 'de, R: ?Sized> {
+	len: usize,
+	seq: bool,
+	de: &'a mut BytesDe<'de, R>,
+}
+
+pub struct BytesDeIter<'a, 'de, R: ?Sized, T> {
+	len: usize,
+	de: &'a mut BytesDe<'de, R>,
+	_item: PhantomData<T>,
+}
+
+impl<'de, R: ?Sized + Read> BytesDe<'de, R> {
+	// `depth` bounds how deeply options, sequences, maps, newtypes and enums can
+	// nest, so that untrusted input can't overflow the stack; the other
+	// constructors don't limit it
+	pub fn with_limits(read: &'de mut R, alloc: usize, depth: usize) -> Self {
+		Self {
+			read, alloc, depth,
+			consumed: 0,
+			mode: Mode::Build,
+			ahead: [0; 64],
+			ahead_pos: 0,
+			ahead_len: 0,
+		}
+	}
+
+	pub fn with_alloc_limit(read: &'de mut R, limit: usize) -> Self {
+		Self::with_limits(read, limit, usize::max_value())
+	}
+
+	pub fn new(read: &'de mut R) -> Self { Self::with_alloc_limit(read, 1 << 24) }
+
+	pub fn end(self) -> &'de mut R { self.read }
+
+	pub fn deserialize<T: serde::Deserialize<'de>>(&mut self) -> Result<T> {
+		T::deserialize(self)
+	}
+
+	// runs `T`'s `Deserialize` impl over the next value, but hands it empty
+	// strings, bytes, sequences and maps instead of reading them into memory;
+	// returns the number of bytes consumed
+	//
+	// since `T` only ever sees empty contents, impls that check them, like a
+	// non-empty string or a fixed-length array read as a sequence, reject
+	// valid input here
+	pub fn validate<T: serde::Deserialize<'de>>(&mut self) -> Result<usize> {
+		self.discard::<T>(Mode::Validate)
+	}
+
+	// like `validate`, but doesn't check that strings are UTF-8
+	pub fn skip<T: serde::Deserialize<'de>>(&mut self) -> Result<usize> {
+		self.discard::<T>(Mode::Skip)
+	}
+
+	fn discard<T: serde::Deserialize<'de>>(&mut self, mode: Mode) -> Result<usize> {
+		let (start, outer) = (self.consumed, self.mode);
+		self.mode = mode;
+		let result = T::deserialize(&mut *self);
+		self.mode = outer;
+		result.map(|_| self.consumed - start)
+	}
+
+	// reads a sequence's length, then its elements lazily; unlike
+	// `deserialize_seq`, the length isn't counted against the alloc limit
+	pub fn seq_iter<T>(&mut self) -> Result<BytesDeIter<'_, 'de, R, T>> where
+		T: serde::Deserialize<'de>,
+	{
+		let len = self.de_usize()?;
+		Ok(BytesDeIter { len, de: self, _item: PhantomData })
+	}
+
+	pub fn map_iter<K, V>(&mut self) -> Result<BytesDeIter<'_, 'de, R, (K, V)>> where
+		K: serde::Deserialize<'de>,
+		V: serde::Deserialize<'de>,
+	{
+		self.seq_iter()
+	}
+
+	// runs `f` on a `BytesDe` that reads through `dyn Read`, carrying the limits,
+	// counters and read-ahead bytes over to it and back
+	pub(super) fn with_dyn_read<T>(
+		&mut self, f: impl FnOnce(&mut BytesDe<'_, dyn Read + '_>) -> T,
+	) -> T where
+		R: Sized,
+	{
+		let mut de = BytesDe::<dyn Read> {
+			read: &mut *self.read,
+			alloc: self.alloc,
+			depth: self.depth,
+			consumed: self.consumed,
+			mode: self.mode,
+			ahead: self.ahead,
+			ahead_pos: self.ahead_pos,
+			ahead_len: self.ahead_len,
+		};
+
+		let value = f(&mut de);
+
+		self.alloc = de.alloc;
+		self.consumed = de.consumed;
+		self.ahead = de.ahead;
+		self.ahead_pos = de.ahead_pos;
+		self.ahead_len = de.ahead_len;
+
+		value
+	}
+
+	fn byte(&mut self) -> Result<u8> {
+		if self.ahead_pos < self.ahead_len {
+			let byte = self.ahead[self.ahead_pos as usize];
+			self.ahead_pos += 1;
+			self.consumed += 1;
+			return Ok(byte);
+		}
+
+		if let Some(buffer) = self.read.buffer() {
+			let byte = match buffer.map_err(Error::Io)?.first() {
+				Some(&byte) => byte,
+				None => return eof(),
+			};
+			self.read.consume(1);
+			self.consumed += 1;
+			return Ok(byte);
+		}
+
+		let mut byte = [0u8];
+		match self.read.read(&mut byte) {
+			Ok(0) => eof(),
+			Err(e) => Err(Error::Io(e)),
+			_ => { self.consumed += 1; Ok(byte[0]) },
+		}
+	}
+
+	pub(super) fn rex(&mut self, to: &mut [u8]) -> Result {
+		let ahead = &self.ahead[self.ahead_pos as usize .. self.ahead_len as usize];
+		let n = ahead.len().min(to.len());
+		to[..n].copy_from_slice(&ahead[..n]);
+		self.ahead_pos += n as u8;
+
+		if n < to.len() { self.read.read_exact(&mut to[n..]).map_err(Error::Io)?; }
+		self.consumed += to.len();
+		Ok(())
+	}
+
+	// reads up to `n` bytes that are known to belong to the value being
+	// deserialized, so that `byte` can hand them out without a `Read::read` each;
+	// buffered readers don't need it
+	fn read_ahead(&mut self, n: usize) -> Result {
+		if self.ahead_pos < self.ahead_len || self.read.buffer().is_some() {
+			return Ok(());
+		}
+
+		let n = n.min(self.ahead.len());
+		self.read.read_exact(&mut self.ahead[..n]).map_err(Error::Io)?;
+		self.ahead_pos = 0;
+		self.ahead_len = n as u8;
+		Ok(())
+	}
+
+	fn nest<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+		if self.depth == 0 { return Err(Error::DepthExceeded); }
+		self.depth -= 1;
+		let result = f(self);
+		self.depth += 1;
+		result
+	}
+
+	fn consume_alloc(&mut self, n: usize) -> Result {
+		if n > self.alloc {
+			Err(Error::AllocExceeded)
+		} else {
+			self.alloc -= n;
+			Ok(())
+		}
+	}
+
+	fn de_u16(&mut self) -> Result<u16> { self.varint() }
+
+	pub(super) fn de_usize(&mut self) -> Result<usize> { self.varint().map(|Len(n)| n) }
+
+	fn de_u32(&mut self) -> Result<u32> { self.varint() }
+
+	fn de_u64(&mut self) -> Result<u64> { self.varint() }
+
+	fn de_u128(&mut self) -> Result<u128> { self.varint() }
+
+	// decodes straight out of the reader's buffer when it holds the longest
+	// encoding of `T`, and a byte at a time otherwise
+	#[inline]
+	fn varint<T: Varint>(&mut self) -> Result<T> {
+		if self.ahead_pos == self.ahead_len {
+			if let Some(buffer) = self.read.buffer() {
+				let mut buffer = buffer.map_err(Error::Io)?;
+				if buffer.len() >= T::MAX {
+					let len = buffer.len();
+					let value = T::decode(&mut buffer);
+					let n = len - buffer.len();
+					self.read.consume(n);
+					self.consumed += n;
+					return value;
+				}
+			}
+		}
+
+		T::decode(self)
+	}
+
+	pub(super) fn de_usize_alloc(&mut self) -> Result<usize> {
+		let len = self.de_usize()?;
+		self.consume_alloc(len)?;
+		Ok(len)
+	}
+
+	fn de_usize_buf(&mut self) -> Result<Vec<u8>> {
+		let mut buf = vec![0u8; self.de_usize_alloc()?];
+		self.rex(&mut buf)?;
+		Ok(buf)
+	}
+
+	// reads past a length-prefixed buffer in chunks, carrying an incomplete
+	// UTF-8 sequence over to the next chunk
+	fn discard_usize_buf(&mut self, utf8: bool) -> Result {
+		let len = self.de_usize_alloc()?;
+		self.discard_buf(len, utf8)
+	}
+
+	pub(super) fn discard_buf(&mut self, mut len: usize, utf8: bool) -> Result {
+		let mut buf = [0u8; 256];
+		let mut carry = 0;
+
+		while len > 0 {
+			let n = len.min(buf.len() - carry);
+			self.rex(&mut buf[carry .. carry + n])?;
+			len -= n;
+
+			let filled = carry + n;
+			carry = 0;
+
+			if !utf8 { continue; }
+
+			if let Err(e) = std::str::from_utf8(&buf[..filled]) {
+				if e.error_len().is_some() || len == 0 { return Err(Error::Utf8(e)); }
+				carry = filled - e.valid_up_to();
+				buf.copy_within(e.valid_up_to() .. filled, 0);
+			}
+		}
+
+		Ok(())
+	}
+
+	pub(super) fn discarding(&self) -> bool { self.mode != Mode::Build }
+
+	fn de_bool(&mut self) -> Result<bool> {
+		Ok(match self.byte()? {
+			0 => false,
+			1 => true,
+			n => return Err(Error::InvalidBool(n)),
+		})
+	}
+}
+
 fn resign<T, U>(v: T) -> U where
 	T: num_traits::One + std::ops::BitAnd<Output = T>
 		+ std::ops::Shr<u32, Output = T> + num_traits::AsPrimitive<U> + Eq,
@@ -240,7 +573,7 @@ fn resign<T, U>(v: T) -> U where
 	}
 }
 
-impl<'a, 'de, R: Read> Deserializer<'de> for &'a mut BytesDe<'de, R> {
+impl<'a, 'de, R: ?Sized + Read> Deserializer<'de> for &'a mut BytesDe<'de, R> {
 	type Error = Error;
 
 	fn deserialize_bool<V: Visitor<'de>>(self, v: V) -> Result<V::Value> {
@@ -303,9 +636,14 @@ impl<'a, 'de, R: Read> Deserializer<'de> for &'a mut BytesDe<'de, R> {
 		v.visit_char(match self.byte()? {
 			n@0..=0x7F => n as u8 as char,
 			n => {
-				let mut bytes = [0u8; 6];
+				let mut bytes = [0u8; 4];
 				bytes[0] = n;
-				let len = n.leading_ones() as usize;
+				// a continuation byte or a lead byte for more than 4 bytes fails
+				// to decode on its own
+				let len = match n.leading_ones() {
+					len@2..=4 => len as usize,
+					_ => 1,
+				};
 				self.rex(&mut bytes[1 .. len])?;
 				match std::str::from_utf8(&bytes[.. len])
 					.map_err(Error::Utf8)?.chars().next()
@@ -318,12 +656,22 @@ impl<'a, 'de, R: Read> Deserializer<'de> for &'a mut BytesDe<'de, R> {
 	}
 
 	fn deserialize_str<V: Visitor<'de>>(self, v: V) -> Result<V::Value> {
+		if self.discarding() {
+			self.discard_usize_buf(self.mode == Mode::Validate)?;
+			return v.visit_str("");
+		}
+
 		v.visit_str(
 			std::str::from_utf8(&self.de_usize_buf()?).map_err(Error::Utf8)?
 		)
 	}
 
 	fn deserialize_string<V: Visitor<'de>>(self, v: V) -> Result<V::Value> {
+		if self.discarding() {
+			self.discard_usize_buf(self.mode == Mode::Validate)?;
+			return v.visit_string(String::new());
+		}
+
 		v.visit_string(
 			String::from_utf8(self.de_usize_buf()?)
 				.map_err(|e| Error::Utf8(e.utf8_error()))?
@@ -331,16 +679,21 @@ impl<'a, 'de, R: Read> Deserializer<'de> for &'a mut BytesDe<'de, R> {
 	}
 
 	fn deserialize_bytes<V: Visitor<'de>>(self, v: V) -> Result<V::Value> {
+		if self.discarding() {
+			self.discard_usize_buf(false)?;
+			return v.visit_bytes(&[]);
+		}
+
 		v.visit_bytes(&self.de_usize_buf()?)
 	}
 
 	fn deserialize_byte_buf<V: Visitor<'de>>(self, v: V) -> Result<V::Value> {
-		v.visit_byte_buf(self.de_usize_buf()?)
+		v.read_buf(self)
 	}
 
 	fn deserialize_option<V: Visitor<'de>>(self, v: V) -> Result<V::Value> {
 		if self.de_bool()? {
-			v.visit_some(self)
+			self.nest(|de| v.visit_some(de))
 		} else {
 			v.visit_none()
 		}
@@ -359,41 +712,41 @@ impl<'a, 'de, R: Read> Deserializer<'de> for &'a mut BytesDe<'de, R> {
 	fn deserialize_newtype_struct<V: Visitor<'de>>(
 		self, _name: &'static str, v: V,
 	) -> Result<V::Value> {
-		v.visit_newtype_struct(self)
+		self.nest(|de| v.visit_newtype_struct(de))
 	}
 
 	fn deserialize_seq<V: Visitor<'de>>(self, v: V) -> Result<V::Value> {
 		let len = self.de_usize_alloc()?;
-		v.visit_seq(BytesDeLen { len, de: self })
+		self.nest(|de| v.visit_seq(BytesDeLen { len, seq: true, de }))
 	}
 
 	fn deserialize_tuple<V: Visitor<'de>>(
 		self, len: usize, v: V,
 	) -> Result<V::Value> {
-		v.visit_seq(BytesDeLen { len, de: self })
+		self.nest(|de| v.visit_seq(BytesDeLen { len, seq: false, de }))
 	}
 
 	fn deserialize_tuple_struct<V: Visitor<'de>>(
 		self, _name: &'static str, len: usize, v: V,
 	) -> Result<V::Value> {
-		v.visit_seq(BytesDeLen { len, de: self })
+		self.deserialize_tuple(len, v)
 	}
 
 	fn deserialize_map<V: Visitor<'de>>(self, v: V) -> Result<V::Value> {
 		let len = self.de_usize_alloc()?;
-		v.visit_seq(BytesDeLen { len, de: self })
+		self.nest(|de| v.visit_map(BytesDeLen { len, seq: true, de }))
 	}
 
 	fn deserialize_struct<V: Visitor<'de>>(
 		self, _name: &'static str, fields: &'static [&'static str], v: V,
 	) -> Result<V::Value> {
-		v.visit_seq(BytesDeLen { len: fields.len(), de: self })
+		self.deserialize_tuple(fields.len(), v)
 	}
 
 	fn deserialize_enum<V: Visitor<'de>>(
 		self, _name: &'static str, _variants: &'static [&'static str], v: V,
 	) -> Result<V::Value> {
-		v.visit_enum(self)
+		self.nest(|de| v.visit_enum(de))
 	}
 
 	fn deserialize_identifier<V: Visitor<'de>>(self, _: V) -> Result<V::Value> {
@@ -411,17 +764,95 @@ impl<'a, 'de, R: Read> Deserializer<'de> for &'a mut BytesDe<'de, R> {
 	fn is_human_readable(&self) -> bool { false }
 }
 
+// visitors that can decode a buffer straight out of the reader, instead of
+// from a `Vec<u8>` copy of it
+pub(super) trait ReadBuf<'de>: Visitor<'de> {
+	fn read_buf<R: ?Sized + Read>(self, de: &mut BytesDe<'de, R>) -> Result<Self::Value>;
+}
+
+impl<'de, V: Visitor<'de>> ReadBuf<'de> for V {
+	default fn read_buf<R: ?Sized + Read>(self, de: &mut BytesDe<'de, R>) -> Result<V::Value> {
+		if de.discarding() {
+			de.discard_usize_buf(false)?;
+			return self.visit_byte_buf(Vec::new());
+		}
+
+		self.visit_byte_buf(de.de_usize_buf()?)
+	}
+}
+
+// seeds that can be made again, so that discarding a sequence or map can read
+// all of its elements without handing any of them to the visitor
+trait ReuseSeed: Sized {
+	fn reuse(&self) -> Option<Self>;
+}
+
+impl<S> ReuseSeed for S {
+	default fn reuse(&self) -> Option<Self> { None }
+}
+
+impl<T> ReuseSeed for PhantomData<T> {
+	fn reuse(&self) -> Option<Self> { Some(PhantomData) }
+}
+
+// seeds for single-byte elements; if a sequence's next element is a byte then
+// (barring zero-sized elements) so many bytes are left in it as elements
+trait ByteSeed {
+	fn is_byte() -> bool;
+}
+
+impl<S> ByteSeed for S {
+	default fn is_byte() -> bool { false }
+}
+
+impl ByteSeed for PhantomData<u8> {
+	fn is_byte() -> bool { true }
+}
+
+impl ByteSeed for PhantomData<i8> {
+	fn is_byte() -> bool { true }
+}
+
+impl<'a, 'de, R: ?Sized + Read> BytesDeLen<'a, 'de, R> {
+	fn discard_rest<K, V>(&mut self, key: &K, value: Option<&V>) -> Result<bool> where
+		K: DeserializeSeed<'de>,
+		V: DeserializeSeed<'de>,
+	{
+		if !self.seq || !self.de.discarding() { return Ok(false); }
+
+		while self.len > 0 {
+			match (key.reuse(), value.map(V::reuse)) {
+				(Some(key), None) => {
+					if K::is_byte() { self.de.read_ahead(self.len)?; }
+					key.deserialize(&mut *self.de)?;
+				},
+				(Some(key), Some(Some(value))) => {
+					key.deserialize(&mut *self.de)?;
+					value.deserialize(&mut *self.de)?;
+				},
+				_ => return Ok(false),
+			}
+			self.len -= 1;
+		}
+
+		Ok(true)
+	}
+}
+
 impl<'a, 'de, R> serde::de::SeqAccess<'de> for BytesDeLen<'a, 'de, R> where
-	R: Read,
+	R: ?Sized + Read,
 {
 	type Error = Error;
 
 	fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>> where
 		T: serde::de::DeserializeSeed<'de>,
 	{
+		if self.discard_rest(&seed, None::<&T>)? { return Ok(None); }
+
 		Ok(if self.len == 0 {
 			None
 		} else {
+			if self.seq && T::is_byte() { self.de.read_ahead(self.len)?; }
 			self.len -= 1;
 			Some(seed.deserialize(&mut *self.de)?)
 		})
@@ -430,8 +861,30 @@ impl<'a, 'de, R> serde::de::SeqAccess<'de> for BytesDeLen<'a, 'de, R> where
 	fn size_hint(&self) -> Option<usize> { Some(self.len) }
 }
 
+impl<'a, 'de, R, T> Iterator for BytesDeIter<'a, 'de, R, T> where
+	R: ?Sized + Read,
+	T: serde::Deserialize<'de>,
+{
+	type Item = Result<T>;
+
+	fn next(&mut self) -> Option<Result<T>> {
+		if self.len == 0 {
+			None
+		} else {
+			self.len -= 1;
+			let result = self.de.deserialize::<T>();
+			if result.is_err() { self.len = 0; }
+			Some(result)
+		}
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		(self.len.min(1), Some(self.len))
+	}
+}
+
 impl<'a, 'de, R> serde::de::MapAccess<'de> for BytesDeLen<'a, 'de, R> where
-	R: Read,
+	R: ?Sized + Read,
 {
 	type Error = Error;
 
@@ -452,11 +905,27 @@ impl<'a, 'de, R> serde::de::MapAccess<'de> for BytesDeLen<'a, 'de, R> where
 		Ok(seed.deserialize(&mut *self.de)?)
 	}
 
+	fn next_entry_seed<K, V>(&mut self, key: K, value: V) ->
+		Result<Option<(K::Value, V::Value)>>
+	where
+		K: serde::de::DeserializeSeed<'de>,
+		V: serde::de::DeserializeSeed<'de>,
+	{
+		if self.discard_rest(&key, Some(&value))? { return Ok(None); }
+
+		Ok(if self.len == 0 {
+			None
+		} else {
+			self.len -= 1;
+			Some((key.deserialize(&mut *self.de)?, value.deserialize(&mut *self.de)?))
+		})
+	}
+
 	fn size_hint(&self) -> Option<usize> { Some(self.len) }
 }
 
 impl<'a, 'de, R> serde::de::EnumAccess<'de> for &'a mut BytesDe<'de, R> where
-	R: Read,
+	R: ?Sized + Read,
 {
 	type Error = Error;
 	type Variant = Self;
@@ -470,7 +939,7 @@ impl<'a, 'de, R> serde::de::EnumAccess<'de> for &'a mut BytesDe<'de, R> where
 }
 
 impl<'a, 'de, R> serde::de::VariantAccess<'de> for &'a mut BytesDe<'de, R> where
-	R: Read,
+	R: ?Sized + Read,
 {
 	type Error = Error;
 
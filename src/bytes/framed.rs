@@ -0,0 +1,157 @@
+use {
+	super::{BytesSer, BytesDe, Error},
+	serde::{Serialize, de::DeserializeOwned},
+	tokio::io::{AsyncRead, AsyncWrite, AsyncReadExt, AsyncWriteExt},
+};
+
+type Result<T = ()> = std::result::Result<T, Error>;
+
+// frames are encoded like `serialize_bytes`: a length, then that many bytes
+
+pub struct FramedWriter<W> {
+	write: W,
+	ser: BytesSer,
+	header: BytesSer,
+}
+
+pub struct FramedReader<R> {
+	read: R,
+	buffer: Vec<u8>,
+	alloc: usize,
+}
+
+impl<W: AsyncWrite + Unpin> FramedWriter<W> {
+	pub fn new(write: W) -> Self {
+		Self { write, ser: BytesSer::new(), header: BytesSer::new() }
+	}
+
+	pub fn get_mut(&mut self) -> &mut W { &mut self.write }
+
+	pub fn into_inner(self) -> W { self.write }
+
+	pub async fn send<T: Serialize>(&mut self, value: &T) -> Result {
+		self.ser.clear();
+		self.ser.serialize(value).map_err(Error::Ser)?;
+
+		self.header.clear();
+		self.header.ser_usize(self.ser.len());
+
+		for slice in self.header.slices().chain(self.ser.slices()) {
+			self.write.write_all(slice).await.map_err(Error::Io)?;
+		}
+
+		Ok(())
+	}
+
+	pub async fn flush(&mut self) -> Result {
+		self.write.flush().await.map_err(Error::Io)
+	}
+}
+
+impl<R: AsyncRead + Unpin> FramedReader<R> {
+	// like `BytesDe`'s, the limit covers both the frame and what decoding it
+	// allocates, but it's reset for every frame
+	pub fn with_alloc_limit(read: R, limit: usize) -> Self {
+		Self { read, buffer: Vec::new(), alloc: limit }
+	}
+
+	pub fn new(read: R) -> Self { Self::with_alloc_limit(read, 1 << 24) }
+
+	pub fn get_mut(&mut self) -> &mut R { &mut self.read }
+
+	pub fn into_inner(self) -> R { self.read }
+
+	// `Ok(None)` when the stream ends cleanly between two frames; a frame over
+	// the limit is skipped, so reading can go on after its `AllocExceeded`, but
+	// after any other error the stream is out of step with the frames
+	pub async fn recv<T: DeserializeOwned>(&mut self) -> Result<Option<T>> {
+		let mut header = [0u8; (usize::BITS as usize + 6) / 7];
+		let mut header_len = 0;
+
+		loop {
+			let byte = match self.read.read_u8().await {
+				Ok(byte) => byte,
+				Err(e) if header_len == 0
+					&& e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+				Err(e) => return Err(Error::Io(e)),
+			};
+
+			header[header_len] = byte;
+			header_len += 1;
+
+			if byte < 0x80 { break; }
+			if header_len == header.len() { return Err(Error::AllocExceeded); }
+		}
+
+		let len = BytesDe::new(&mut &header[..header_len]).de_usize()?;
+
+		if len > self.alloc {
+			let mut frame = (&mut self.read).take(len as u64);
+			tokio::io::copy(&mut frame, &mut tokio::io::sink()).await.map_err(Error::Io)?;
+			return Err(Error::AllocExceeded);
+		}
+
+		self.buffer.clear();
+		self.buffer.resize(len, 0);
+		self.read.read_exact(&mut self.buffer).await.map_err(Error::Io)?;
+
+		let mut slice = &*self.buffer;
+		let value = BytesDe::with_alloc_limit(&mut slice, self.alloc - len)
+			.deserialize::<T>()?;
+
+		if slice.is_empty() {
+			Ok(Some(value))
+		} else {
+			Err(Error::Custom(format!("{} trailing bytes in frame", slice.len())))
+		}
+	}
+}
+
+#[test]
+fn test() {
+	let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+
+	let values = (0..200u32)
+		.map(|i| (i, "y".repeat(i as usize * 3), vec![i as i64 - 100; i as usize]))
+		.collect::<Vec<_>>();
+
+	rt.block_on(async {
+		let (client, server) = tokio::io::duplex(64);
+
+		let sent = values.clone();
+		let writer = tokio::spawn(async move {
+			let mut writer = FramedWriter::new(client);
+			for value in &sent { writer.send(value).await.unwrap(); }
+			// the reader skips this one
+			writer.send(&vec![0u8; 1 << 12]).await.unwrap();
+			writer.send(&vec![1u8]).await.unwrap();
+		});
+
+		let mut reader = FramedReader::with_alloc_limit(server, 1 << 11);
+
+		for value in &values {
+			assert_eq!(
+				reader.recv::<(u32, String, Vec<i64>)>().await.unwrap().as_ref(),
+				Some(value),
+			);
+		}
+
+		assert!(matches!(reader.recv::<Vec<u8>>().await, Err(Error::AllocExceeded)));
+		assert_eq!(reader.recv::<Vec<u8>>().await.unwrap(), Some(vec![1]));
+		assert!(reader.recv::<Vec<u8>>().await.unwrap().is_none());
+
+		writer.await.unwrap();
+	});
+
+	rt.block_on(async {
+		let (client, server) = tokio::io::duplex(64);
+
+		let mut writer = FramedWriter::new(client);
+		writer.send(&String::from("last")).await.unwrap();
+		drop(writer);
+
+		let mut reader = FramedReader::new(server);
+		assert_eq!(reader.recv::<String>().await.unwrap().as_deref(), Some("last"));
+		assert!(reader.recv::<String>().await.unwrap().is_none());
+	});
+}
@@ -0,0 +1,272 @@
+use {
+	super::{BytesSer, BytesDe, Error},
+	serde::{Serialize, de::DeserializeOwned},
+	std::{
+		fs::{File, OpenOptions},
+		hash::Hasher,
+		io::{self, Read, Write, Seek, SeekFrom, BufReader},
+		marker::PhantomData,
+		path::Path,
+	},
+};
+
+type Result<T = ()> = std::result::Result<T, Error>;
+
+// each record is `(len: u64, checksum: [u8; 8])` as encoded by `BytesSer`,
+// followed by `len` bytes of payload; the checksum only covers the payload
+
+pub struct Log {
+	file: File,
+	ser: BytesSer,
+	header: BytesSer,
+	len: usize,
+	end: u64,
+}
+
+pub struct LogIter<'a, T> {
+	reader: Counted<BufReader<&'a mut File>>,
+	payload: Vec<u8>,
+	_item: PhantomData<T>,
+}
+
+struct Counted<R> { read: R, pos: u64, end: u64 }
+
+enum Record { Read, End, Torn }
+
+impl<R: Read> Read for Counted<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let n = self.read.read(buf)?;
+		self.pos += n as u64;
+		Ok(n)
+	}
+}
+
+fn checksum<'a>(slices: impl IntoIterator<Item = &'a [u8]>) -> u64 {
+	let mut hasher = crate::maps::sht::Hasher::default();
+	for slice in slices { hasher.write(slice); }
+	hasher.finish()
+}
+
+fn is_eof(error: &Error) -> bool {
+	matches!(error, Error::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof)
+}
+
+impl<R: Read> Counted<R> {
+	fn record(&mut self, payload: &mut Vec<u8>) -> Result<Record> {
+		if self.pos == self.end {
+			return Ok(Record::End);
+		}
+
+		let (len, sum) = match BytesDe::new(self).deserialize::<(u64, [u8; 8])>() {
+			Ok(header) => header,
+			Err(e) if is_eof(&e) => return Ok(Record::Torn),
+			Err(e) => return Err(e),
+		};
+
+		if len > self.end - self.pos {
+			return Ok(Record::Torn);
+		}
+
+		payload.clear();
+		payload.resize(len as usize, 0);
+
+		match self.read_exact(payload) {
+			Ok(()) => {},
+			Err(e) if e.kind() == io::ErrorKind::UnexpectedEof =>
+				return Ok(Record::Torn),
+			Err(e) => return Err(Error::Io(e)),
+		}
+
+		Ok(if checksum(Some(&**payload)) == u64::from_le_bytes(sum) {
+			Record::Read
+		} else if self.pos == self.end {
+			Record::Torn
+		} else {
+			// not a torn write, since more records follow
+			return Err(Error::InvalidRecord);
+		})
+	}
+}
+
+impl Log {
+	// opens or creates the log at `path`, truncating a torn final record; a
+	// corrupted record anywhere else is an error, so the ones after it aren't lost
+	pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+		let mut file = OpenOptions::new()
+			.read(true).write(true).create(true)
+			.open(path).map_err(Error::Io)?;
+
+		let file_len = file.metadata().map_err(Error::Io)?.len();
+
+		let mut reader = Counted {
+			read: BufReader::new(&mut file),
+			pos: 0,
+			end: file_len,
+		};
+
+		let mut payload = Vec::new();
+		let mut len = 0;
+		let mut end = 0;
+
+		while let Record::Read = reader.record(&mut payload)? {
+			len += 1;
+			end = reader.pos;
+		}
+
+		if end != file_len {
+			file.set_len(end).map_err(Error::Io)?;
+			file.sync_all().map_err(Error::Io)?;
+		}
+
+		Ok(Self { file, ser: BytesSer::new(), header: BytesSer::new(), len, end })
+	}
+
+	pub fn len(&self) -> usize { self.len }
+
+	pub fn is_empty(&self) -> bool { self.len == 0 }
+
+	pub fn append<T: Serialize>(&mut self, value: &T) -> Result {
+		self.ser.clear();
+		self.ser.serialize(value).map_err(Error::Ser)?;
+
+		let len = self.ser.len();
+
+		self.header.clear();
+		self.header.serialize(
+			&(len as u64, checksum(self.ser.slices()).to_le_bytes())
+		).map_err(Error::Ser)?;
+
+		let Self { file, ser, header, .. } = self;
+
+		let written = file.seek(SeekFrom::Start(self.end))
+			.and_then(|_| {
+				for slice in header.slices().chain(ser.slices()) {
+					file.write_all(slice)?;
+				}
+				Ok(())
+			});
+
+		if let Err(e) = written {
+			let _ = file.set_len(self.end);
+			return Err(Error::Io(e));
+		}
+
+		self.end += (self.header.len() + len) as u64;
+		self.len += 1;
+
+		Ok(())
+	}
+
+	pub fn sync(&self) -> Result { self.file.sync_data().map_err(Error::Io) }
+
+	pub fn iter<T: DeserializeOwned>(&mut self) -> Result<LogIter<'_, T>> {
+		self.file.seek(SeekFrom::Start(0)).map_err(Error::Io)?;
+		Ok(LogIter {
+			reader: Counted { read: BufReader::new(&mut self.file), pos: 0, end: self.end },
+			payload: Vec::new(),
+			_item: PhantomData,
+		})
+	}
+}
+
+impl<T: DeserializeOwned> Iterator for LogIter<'_, T> {
+	type Item = Result<T>;
+
+	fn next(&mut self) -> Option<Result<T>> {
+		match self.reader.record(&mut self.payload) {
+			Ok(Record::Read) => {
+				let mut slice = &*self.payload;
+				Some(BytesDe::new(&mut slice).deserialize::<T>().and_then(|value|
+					if slice.is_empty() { Ok(value) } else { Err(Error::InvalidRecord) }
+				))
+			},
+			Ok(Record::End) => None,
+			Ok(Record::Torn) => {
+				self.reader.end = self.reader.pos;
+				Some(Err(Error::InvalidRecord))
+			},
+			Err(e) => {
+				self.reader.end = self.reader.pos;
+				Some(Err(e))
+			},
+		}
+	}
+}
+
+#[test]
+fn test() {
+	let path = std::env::temp_dir()
+		.join(format!("utl-bytes-log-{}", std::process::id()));
+
+	let _ = std::fs::remove_file(&path);
+
+	let records = (0..100u32)
+		.map(|i| (i, "x".repeat(i as usize), vec![i as u64 * 1_000_000; i as usize % 7]))
+		.collect::<Vec<_>>();
+
+	let read = |log: &mut Log| log
+		.iter::<(u32, String, Vec<u64>)>().unwrap()
+		.collect::<Result<Vec<_>>>().unwrap();
+
+	{
+		let mut log = Log::open(&path).unwrap();
+		assert!(log.is_empty());
+		for record in &records[..50] { log.append(record).unwrap(); }
+		assert_eq!(read(&mut log), &records[..50]);
+		for record in &records[50..] { log.append(record).unwrap(); }
+		log.sync().unwrap();
+	}
+
+	let full_len = std::fs::metadata(&path).unwrap().len();
+
+	{
+		let mut log = Log::open(&path).unwrap();
+		assert_eq!(log.len(), records.len());
+		assert_eq!(read(&mut log), records);
+	}
+
+	fn encoded_len(value: &impl Serialize) -> u64 {
+		let mut ser: BytesSer = BytesSer::new();
+		ser.serialize(value).unwrap();
+		ser.bytes().count() as u64
+	}
+	let payload_len = encoded_len(&records[99]);
+	let header_len = encoded_len(&(payload_len, [0u8; 8]));
+
+	// torn twice inside the header, then twice inside the payload, then a
+	// corrupted payload
+	let tears = [1, 3, header_len + 1, header_len + payload_len - 1];
+	for tear in tears.iter().cloned().map(Some).chain(Some(None)) {
+		{
+			let mut log = Log::open(&path).unwrap();
+			log.append(&records[99]).unwrap();
+		}
+
+		let len = std::fs::metadata(&path).unwrap().len();
+		assert_eq!(len, full_len + header_len + payload_len);
+		let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+
+		match tear {
+			Some(tear) => file.set_len(full_len + tear).unwrap(),
+			None => {
+				file.seek(SeekFrom::Start(len - 1)).unwrap();
+				file.write_all(&[0xFF]).unwrap();
+			},
+		}
+
+		let mut log = Log::open(&path).unwrap();
+		assert_eq!(log.len(), records.len());
+		assert_eq!(std::fs::metadata(&path).unwrap().len(), full_len);
+		assert_eq!(read(&mut log), records);
+	}
+
+	// corrupting the first record leaves the log alone
+	let first_len = encoded_len(&records[0]);
+	let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+	file.seek(SeekFrom::Start(encoded_len(&(first_len, [0u8; 8])) + first_len - 1)).unwrap();
+	file.write_all(&[0xFF]).unwrap();
+	assert!(matches!(Log::open(&path), Err(Error::InvalidRecord)));
+	assert_eq!(std::fs::metadata(&path).unwrap().len(), full_len);
+
+	std::fs::remove_file(&path).unwrap();
+}
@@ -1,32 +1,51 @@
 mod ser;
 mod de;
+mod log;
+mod ord;
+mod shared;
+mod registry;
+mod pod;
+mod schema;
+mod transcode;
+#[cfg(feature = "tokio")] mod framed;
 
 //TODO switch to little-endian
 
 pub use self::{
-	ser::BytesSer,
-	de::{BytesDe, Error},
+	ser::{BytesSer, Error as SerError},
+	de::{BytesDe, BytesDeIter, Buffered, Error},
+	log::{Log, LogIter},
+	ord::{OrdSer, OrdDe},
+	shared::{Shared, SharedPtr, shared_scope},
+	registry::{Registry, DynType},
+	pod::{Pod, PodElem, PodSlice, PodBuf},
+	schema::{Schema, Format, Container, VariantFormat},
+	transcode::Transcoder,
 };
 
+#[cfg(feature = "tokio")]
+pub use self::framed::{FramedReader, FramedWriter};
+
+#[cfg(test)]
+fn ck<T>(x: T) where
+	T: serde::Serialize + for<'de> serde::Deserialize<'de> + Eq + std::fmt::Debug,
+{
+	eprint!("{}: {:?}", std::any::type_name::<T>(), &x);
+	let mut ser: BytesSer = BytesSer::new();
+	ser.serialize(&x).unwrap();
+	let data = ser.bytes().collect::<Vec<u8>>();
+	let mut slice = &*data;
+	eprint!(" →"); for byte in slice.iter() { eprint!(" {:08b}", *byte); }
+	let mut de = BytesDe::new(&mut slice);
+	let y = de.deserialize::<T>().unwrap();
+	eprint!(" → {:?} (+{})", &y, slice.len());
+	assert_eq!(x, y);
+	assert!(slice.is_empty());
+	eprintln!("");
+}
+
 #[test]
 fn test() {
-	fn ck<T>(x: T) where
-		T: serde::Serialize + for<'de> serde::Deserialize<'de> + Eq + std::fmt::Debug,
-	{
-		eprint!("{}: {:?}", std::any::type_name::<T>(), &x);
-		let mut ser: BytesSer = BytesSer::new();
-		ser.serialize(&x).unwrap();
-		let data = ser.bytes().collect::<Vec<u8>>();
-		let mut slice = &*data;
-		eprint!(" →"); for byte in slice.iter() { eprint!(" {:08b}", *byte); }
-		let mut de = BytesDe::new(&mut slice);
-		let y = de.deserialize::<T>().unwrap();
-		eprint!(" → {:?} (+{})", &y, slice.len());
-		assert_eq!(x, y);
-		assert!(slice.is_empty());
-		eprintln!("");
-	}
-
 	fn rand_u16() -> u16 {
 		let mut rng = rand::thread_rng();
 		(rng.gen::<u16>() | (1 << 15)) >> rng.gen_range(0..16)
@@ -75,3 +94,797 @@ fn test() {
 	}
 }
 
+#[test]
+fn lengths_and_maps() {
+	use std::collections::{BTreeMap, HashMap};
+
+	fn ck<T>(x: T) where
+		T: serde::Serialize + for<'de> serde::Deserialize<'de> + PartialEq + std::fmt::Debug,
+	{
+		let mut ser: BytesSer = BytesSer::new();
+		ser.serialize(&x).unwrap();
+		let data = ser.bytes().collect::<Vec<u8>>();
+		let mut slice = &*data;
+		assert_eq!(BytesDe::new(&mut slice).deserialize::<T>().unwrap(), x);
+		assert!(slice.is_empty());
+	}
+
+	// lengths whose varint continuation bytes carry into the next group
+	for len in [0x7F, 0x80, 0xFF, 0x100, 0x4000, 0x4080, 10_000] {
+		ck(vec![0u16; len]);
+		ck("x".repeat(len));
+	}
+
+	ck((0..300u32).map(|i| (i, i.to_string())).collect::<BTreeMap<_, _>>());
+	ck((0..300u32).map(|i| (i.to_string(), i)).collect::<HashMap<_, _>>());
+}
+
+#[test]
+fn streaming() {
+	use {serde::de::DeserializeSeed, crate::ser_iter::*, std::collections::BTreeMap};
+
+	let seq = (0..10_000u32).map(|i| i.wrapping_mul(2654435761)).collect::<Vec<_>>();
+	let map = (0..1000u64).map(|i| (i * i, i.to_string())).collect::<BTreeMap<_, _>>();
+
+	let mut ser: BytesSer = BytesSer::new();
+	ser.serialize(&(&seq, &map, &seq, &map)).unwrap();
+	let data = ser.bytes().collect::<Vec<u8>>();
+	let mut slice = &*data;
+	let mut de = BytesDe::new(&mut slice);
+
+	let mut read_seq = Vec::new();
+	DeEach::new(|n: u32| read_seq.push(n)).deserialize(&mut de).unwrap();
+	assert_eq!(read_seq, seq);
+
+	let mut read_map = BTreeMap::new();
+	DeEachEntry::new(|k: u64, v: String| { read_map.insert(k, v); })
+		.deserialize(&mut de).unwrap();
+	assert_eq!(read_map, map);
+
+	let iter = de.seq_iter::<u32>().unwrap();
+	assert_eq!(iter.size_hint(), (1, Some(seq.len())));
+	assert_eq!(iter.collect::<Result<Vec<_>, _>>().unwrap(), seq);
+
+	assert_eq!(
+		de.map_iter::<u64, String>().unwrap()
+			.collect::<Result<BTreeMap<_, _>, _>>().unwrap(),
+		map,
+	);
+
+	assert!(slice.is_empty());
+}
+
+#[test]
+fn ser_iter() {
+	use {crate::ser_iter::*, std::collections::BTreeMap};
+
+	fn bytes(value: &impl serde::Serialize) -> Result<Vec<u8>, SerError> {
+		let mut ser: BytesSer = BytesSer::new();
+		ser.serialize(value)?;
+		Ok(ser.bytes().collect())
+	}
+
+	fn de<T: for<'de> serde::Deserialize<'de>>(data: &[u8]) -> T {
+		let mut slice = data;
+		let value = BytesDe::new(&mut slice).deserialize::<T>().unwrap();
+		assert!(slice.is_empty());
+		value
+	}
+
+	struct Lying(std::ops::Range<u32>, usize);
+
+	impl Iterator for Lying {
+		type Item = u32;
+		fn next(&mut self) -> Option<u32> { self.0.next() }
+		fn size_hint(&self) -> (usize, Option<usize>) { (self.1, Some(self.1)) }
+	}
+
+	let seq = (0..300).collect::<Vec<u32>>();
+
+	assert_eq!(de::<Vec<u32>>(&bytes(&SerTrustedLen::new(0..300u32)).unwrap()), seq);
+	assert_eq!(de::<Vec<u32>>(&bytes(&SerIter::new(0..300u32)).unwrap()), seq);
+
+	assert!(matches!(
+		bytes(&SerTrustedLen::new((0u32..).take_while(|&n| n < 3))),
+		Err(SerError::Custom(_)),
+	));
+	assert!(matches!(bytes(&SerTrustedLen::new(Lying(0..3, 2))), Err(SerError::Custom(_))));
+	assert!(matches!(bytes(&SerTrustedLen::new(Lying(0..3, 4))), Err(SerError::Custom(_))));
+
+	assert!(matches!(
+		bytes(&SerMap::new(Lying(0..3, 4).map(|n| (n, n)))),
+		Err(SerError::LengthMismatch { expected: 4, actual: 3 }),
+	));
+
+	let map = (0..300u32).map(|n| (n, n.to_string())).collect::<BTreeMap<_, _>>();
+
+	assert_eq!(
+		de::<BTreeMap<u32, String>>(&bytes(&SerMap::new(map.clone().into_iter())).unwrap()),
+		map,
+	);
+
+	// unknown lengths, nested, with known-length values around them
+	let nested = SerIter::new((0..200u32).map(|n| (
+		n,
+		SerMap::new(map.iter().filter(move |(&k, _)| k % (n + 1) == 0)),
+		SerIter::new((0..n).filter(|k| k % 3 == 0)),
+	)));
+
+	let expected = (0..200u32).map(|n| (
+		n,
+		map.iter()
+			.filter(|(&k, _)| k % (n + 1) == 0)
+			.map(|(&k, v)| (k, v.clone()))
+			.collect::<BTreeMap<_, _>>(),
+		(0..n).filter(|k| k % 3 == 0).collect::<Vec<_>>(),
+	)).collect::<Vec<_>>();
+
+	assert_eq!(
+		de::<(u8, Vec<(u32, BTreeMap<u32, String>, Vec<u32>)>, u8)>(
+			&bytes(&(7u8, nested, 9u8)).unwrap()
+		),
+		(7, expected, 9),
+	);
+}
+
+#[test]
+fn wrappers() {
+	use crate::{NonMax, ByBits, ByKey, DerefInner, by_key::key_only};
+
+	ck(NonMax::<u32>::none());
+	ck(NonMax::<u32>::some(0));
+	ck(NonMax::<u32>::some(u32::max_value() - 1));
+	ck(NonMax::<u8>::some(254));
+
+	for &x in &[0.0f32, -0.0, 1.5, f32::INFINITY, f32::NAN, f32::MIN_POSITIVE] {
+		ck(ByBits(x));
+		ck(ByBits(x as f64));
+	}
+
+	ck(DerefInner(Box::new(Box::new(7u64))));
+	ck(DerefInner((String::from("a"), 3u16)));
+
+	let mut data = Vec::new();
+
+	let mut ser: BytesSer = BytesSer::new();
+	ser.serialize(&ByKey::new(3u32, String::from("three"))).unwrap();
+	key_only::serialize(&ByKey::new(4u32, String::from("four")), &mut ser).unwrap();
+	ser.serialize(&Some(u8::max_value())).unwrap();
+	data.extend(ser.bytes());
+
+	let mut slice = &*data;
+	let mut de = BytesDe::new(&mut slice);
+
+	let ByKey { key, value } = de.deserialize::<ByKey<u32, String>>().unwrap();
+	assert_eq!((key, &*value), (3, "three"));
+
+	let ByKey { key, value } = key_only::deserialize::<u32, String, _>(&mut de).unwrap();
+	assert_eq!((key, &*value), (4, ""));
+
+	assert!(matches!(de.deserialize::<NonMax<u8>>(), Err(Error::Custom(_))));
+	assert!(slice.is_empty());
+}
+
+#[test]
+fn validate() {
+	use std::collections::{BTreeMap, HashMap};
+
+	type T = (
+		Vec<String>,
+		HashMap<u32, Vec<Option<String>>>,
+		Result<(u8, BTreeMap<String, ()>), Vec<u8>>,
+		[u16; 3],
+	);
+
+	let value: T = (
+		(0..300).map(|i| "é".repeat(i)).collect(),
+		(0..20).map(|i| (i, vec![None, Some("x".repeat(i as usize))])).collect(),
+		Ok((7, (0..5).map(|i| (i.to_string(), ())).collect())),
+		[1, 1000, 0xFFFF],
+	);
+
+	let mut ser: BytesSer = BytesSer::new();
+	ser.serialize(&value).unwrap();
+	let len = ser.len();
+	ser.serialize(&value.3).unwrap();
+	let data = ser.bytes().collect::<Vec<u8>>();
+
+	let mut slice = &*data;
+	let mut de = BytesDe::new(&mut slice);
+	assert_eq!(de.validate::<T>().unwrap(), len);
+	assert_eq!(de.deserialize::<[u16; 3]>().unwrap(), value.3);
+
+	let mut strings: BytesSer = BytesSer::new();
+	strings.serialize(&value.0).unwrap();
+
+	let mut slice = &*data;
+	let mut de = BytesDe::new(&mut slice);
+	assert_eq!(de.skip::<Vec<String>>().unwrap(), strings.len());
+	assert_eq!(de.deserialize::<HashMap<u32, Vec<Option<String>>>>().unwrap(), value.1);
+
+	// a string split across chunks right inside a multi-byte char, then broken
+	let mut ser: BytesSer = BytesSer::new();
+	ser.serialize(&"é".repeat(200)).unwrap();
+	let mut data = ser.bytes().collect::<Vec<u8>>();
+	assert_eq!(BytesDe::new(&mut &*data).validate::<String>().unwrap(), data.len());
+	data[256] = 0xFF;
+	assert!(matches!(BytesDe::new(&mut &*data).validate::<String>(), Err(Error::Utf8(_))));
+	assert!(matches!(BytesDe::new(&mut &*data).deserialize::<String>(), Err(Error::Utf8(_))));
+	assert_eq!(BytesDe::new(&mut &*data).skip::<String>().unwrap(), data.len());
+	assert!(matches!(
+		BytesDe::with_alloc_limit(&mut &*data, 399).skip::<String>(),
+		Err(Error::AllocExceeded),
+	));
+
+	// malformed chars are errors rather than panics whatever the mode
+	for &data in &[&[0xFEu8][..], &[0xFF], &[0x80], &[0xE2, 0x82]] {
+		assert!(BytesDe::new(&mut &*data).validate::<char>().is_err());
+		assert!(BytesDe::new(&mut &*data).skip::<char>().is_err());
+	}
+
+	let mut ser: BytesSer = BytesSer::new();
+	ser.serialize(&vec![vec![vec![Some(1u8)]]]).unwrap();
+	let data = ser.bytes().collect::<Vec<u8>>();
+	for depth in 0..6 {
+		let mut slice = &*data;
+		let mut de = BytesDe::with_limits(&mut slice, 1 << 10, depth);
+		match (depth, de.validate::<Vec<Vec<Vec<Option<u8>>>>>()) {
+			(4.., Ok(len)) => assert_eq!(len, data.len()),
+			(..=3, Err(Error::DepthExceeded)) => {},
+			(_, result) => panic!("{} {:?}", depth, result),
+		}
+	}
+
+	// `Option`s nested as deep as the input goes
+	struct Nested(usize);
+
+	impl<'de> serde::Deserialize<'de> for Nested {
+		fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+			struct V;
+			impl<'de> serde::de::Visitor<'de> for V {
+				type Value = Nested;
+				fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+					f.write_str("an option")
+				}
+				fn visit_none<E>(self) -> Result<Nested, E> { Ok(Nested(0)) }
+				fn visit_some<D: serde::Deserializer<'de>>(self, d: D) -> Result<Nested, D::Error> {
+					<Nested as serde::Deserialize>::deserialize(d).map(|n| Nested(n.0 + 1))
+				}
+			}
+			d.deserialize_option(V)
+		}
+	}
+
+	let mut data = vec![1u8; 1000];
+	data.push(0);
+	assert_eq!(BytesDe::new(&mut &*data).deserialize::<Nested>().unwrap().0, 1000);
+	assert!(matches!(
+		BytesDe::with_limits(&mut &*data, 1 << 10, 999).deserialize::<Nested>(),
+		Err(Error::DepthExceeded),
+	));
+}
+
+#[test]
+fn byte_seqs() {
+	use {crate::{cvec::CVec, ser_iter::SerTrustedLen}, std::io::Read};
+
+	struct Reads<'a> { data: &'a [u8], reads: usize }
+
+	impl Read for Reads<'_> {
+		fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+			self.reads += 1;
+			self.data.read(buf)
+		}
+	}
+
+	let bytes = (0..1000).map(|i| (i * 7) as u8).collect::<Vec<u8>>();
+	let signed = bytes.iter().map(|&b| b as i8).collect::<Vec<i8>>();
+	let value = (bytes.clone(), bytes.iter().copied().collect::<CVec<u8>>(), signed.clone(), 'x');
+
+	let mut ser: BytesSer = BytesSer::new();
+	ser.serialize(&value).unwrap();
+	let data = ser.bytes().collect::<Vec<u8>>();
+
+	// same wire format as the element-by-element path
+	let mut ser: BytesSer = BytesSer::new();
+	ser.serialize(&SerTrustedLen::new(bytes.iter())).unwrap();
+	ser.serialize(&SerTrustedLen::new(bytes.iter())).unwrap();
+	ser.serialize(&SerTrustedLen::new(signed.iter())).unwrap();
+	ser.serialize(&'x').unwrap();
+	assert!(ser.bytes().eq(data.iter().copied()));
+
+	let mut read = Reads { data: &data, reads: 0 };
+	let decoded = BytesDe::new(&mut read)
+		.deserialize::<(Vec<u8>, CVec<u8>, Vec<i8>, char)>().unwrap();
+	assert_eq!(decoded, value);
+	assert!(read.data.is_empty());
+	assert!(read.reads < 3 * (1000 / 64 + 1) + 8, "{} reads", read.reads);
+
+	let mut slice = &*data;
+	let mut de = BytesDe::new(&mut slice);
+	assert_eq!(de.validate::<(Vec<u8>, CVec<u8>, Vec<i8>)>().unwrap(), data.len() - 1);
+	assert_eq!(de.deserialize::<char>().unwrap(), 'x');
+}
+
+#[test]
+fn buffered() {
+	use std::io::BufReader;
+
+	type T = Vec<(u16, u32, u64, i128, Option<usize>)>;
+
+	let value: T = (0..1 << 12u32)
+		.map(|i| {
+			let n = (i as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> (i % 64);
+			(n as _, (n >> 16) as _, n, -(n as i128) << (i % 32), Some(i as _).filter(|i| i % 3 == 0))
+		})
+		.collect();
+
+	let mut ser: BytesSer = BytesSer::new();
+	ser.serialize(&value).unwrap();
+	ser.serialize(&value[7]).unwrap();
+	let data = ser.bytes().collect::<Vec<u8>>();
+
+	let mut slice = Buffered(&*data);
+	let mut de = BytesDe::new(&mut slice);
+	assert_eq!(de.deserialize::<T>().unwrap(), value);
+	assert_eq!(de.deserialize::<(u16, u32, u64, i128, Option<usize>)>().unwrap(), value[7]);
+	assert!(slice.0.is_empty());
+
+	// a small buffer splits varints across refills
+	let mut reader = Buffered(BufReader::with_capacity(7, &*data));
+	let mut de = BytesDe::new(&mut reader);
+	assert_eq!(de.deserialize::<T>().unwrap(), value);
+	assert_eq!(de.deserialize::<(u16, u32, u64, i128, Option<usize>)>().unwrap(), value[7]);
+}
+
+#[test]
+fn shared() {
+	use {crate::SArc, std::{rc::Rc, sync::Arc}};
+
+	type Node = (u32, Vec<Shared<Rc<String>>>, Shared<Arc<Vec<Shared<SArc<u64>>>>>);
+
+	let leaf = Shared(SArc::new(7u64));
+	let string = Shared(Rc::new("x".repeat(100)));
+	let leaves = Shared(Arc::new(vec![leaf.clone(), Shared(SArc::new(8)), leaf.clone()]));
+
+	let nodes: Vec<Node> = (0..10)
+		.map(|i| (i, vec![string.clone(); i as usize % 3], leaves.clone()))
+		.collect();
+
+	let mut ser: BytesSer = BytesSer::new();
+	shared_scope(|| ser.serialize(&nodes)).unwrap();
+	let data = ser.bytes().collect::<Vec<u8>>();
+	assert!(data.len() < 200, "{}", data.len());
+
+	let decoded = shared_scope(|| BytesDe::new(&mut &*data).deserialize::<Vec<Node>>()).unwrap();
+	assert_eq!(decoded, nodes);
+
+	let strings = decoded.iter().flat_map(|(_, strings, _)| strings).collect::<Vec<_>>();
+	assert!(strings.windows(2).all(|w| Rc::ptr_eq(&w[0], &w[1])));
+	assert!(decoded.windows(2).all(|w| Arc::ptr_eq(&(w[0].2).0, &(w[1].2).0)));
+	let leaves = &*(decoded[0].2).0;
+	assert!(SArc::ptr_eq(&leaves[0], &leaves[2]));
+	assert!(!SArc::ptr_eq(&leaves[0], &leaves[1]));
+
+	// scopes are needed, and each starts afresh
+	let mut ser: BytesSer = BytesSer::new();
+	assert!(ser.serialize(&string).is_err());
+	assert!(matches!(BytesDe::new(&mut &*data).deserialize::<Vec<Node>>(), Err(Error::Custom(_))));
+
+	let mut ser: BytesSer = BytesSer::new();
+	shared_scope(|| ser.serialize(&(string.clone(), string.clone()))).unwrap();
+	let data = ser.bytes().collect::<Vec<u8>>();
+	let dangling = &mut &data[data.len() - 2 ..];
+	assert!(matches!(
+		shared_scope(|| BytesDe::new(dangling).deserialize::<Shared<Rc<String>>>()),
+		Err(Error::Custom(_)),
+	));
+}
+
+#[test]
+fn registry() {
+	trait Describe: DynType {
+		fn describe(&self) -> String;
+	}
+
+	impl Describe for u32 {
+		fn describe(&self) -> String { format!("u32 {}", self) }
+	}
+
+	impl Describe for String {
+		fn describe(&self) -> String { format!("string {:?}", self) }
+	}
+
+	impl Describe for Vec<i16> {
+		fn describe(&self) -> String { format!("vec {:?}", self) }
+	}
+
+	let mut registry = Registry::<dyn Describe>::new();
+	registry.register::<u32>(0, "u32").register::<String>(300, "string");
+	assert_eq!(registry.id("string"), Some(300));
+	assert_eq!(registry.name(0), Some("u32"));
+
+	let values: Vec<Box<dyn Describe>> = vec![
+		Box::new(7u32), Box::new(String::from("seven")), Box::new(u32::max_value()),
+	];
+
+	let mut ser: BytesSer = BytesSer::new();
+	for value in &values { registry.serialize(&**value, &mut ser).unwrap(); }
+	assert!(registry.serialize(&vec![7i16], &mut ser).is_err());
+	ser.serialize(&1u8).unwrap();
+	let data = ser.bytes().collect::<Vec<u8>>();
+
+	let mut slice = &*data;
+	let mut de = BytesDe::new(&mut slice);
+	for value in &values {
+		assert_eq!(registry.deserialize(&mut de).unwrap().describe(), value.describe());
+	}
+	assert!(matches!(registry.deserialize(&mut de), Err(Error::UnknownTypeId(1))));
+	assert!(slice.is_empty());
+
+	registry.register::<Vec<i16>>(1, "vec");
+	let mut ser: BytesSer = BytesSer::new();
+	registry.serialize(&vec![-1i16, 2], &mut ser).unwrap();
+	let data = ser.bytes().collect::<Vec<u8>>();
+	let value = registry.deserialize(&mut BytesDe::new(&mut &*data)).unwrap();
+	assert_eq!(value.describe(), "vec [-1, 2]");
+
+	// registering a type twice panics without touching the registry
+	let registered = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+		registry.register::<u32>(2, "u32 again");
+	}));
+	assert!(registered.is_err());
+	assert_eq!((registry.id_of(&7u32), registry.name(2)), (Some(0), None));
+}
+
+#[test]
+fn schema() {
+	use {
+		serde::de::{self, Deserialize, Deserializer, EnumAccess, SeqAccess, VariantAccess},
+		std::{collections::BTreeMap, fmt, time::Duration},
+	};
+
+	// only its schema is traced, so the fields are never read
+	#[allow(dead_code)]
+	enum List { Nil, Cons(u32, Box<List>) }
+
+	impl<'de> Deserialize<'de> for List {
+		fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+			struct Visitor;
+
+			impl<'de> de::Visitor<'de> for Visitor {
+				type Value = List;
+
+				fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result { fmt.write_str("a list") }
+
+				fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<List, A::Error> {
+					match data.variant::<u32>()? {
+						(0, variant) => variant.unit_variant().map(|()| List::Nil),
+						(_, variant) => variant.tuple_variant(2, self),
+					}
+				}
+
+				fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<List, A::Error> {
+					use serde::de::Error as _;
+					let missing = || A::Error::custom("missing field");
+					let head = seq.next_element()?.ok_or_else(missing)?;
+					let tail = seq.next_element()?.ok_or_else(missing)?;
+					Ok(List::Cons(head, tail))
+				}
+			}
+
+			de.deserialize_enum("List", &["Nil", "Cons"], Visitor)
+		}
+	}
+
+	let schema = Schema::trace::<Option<Vec<(Duration, Result<u32, String>)>>>().unwrap();
+	assert_eq!(schema.root, Format::Option(Box::new(Format::Seq(Box::new(Format::Tuple(vec![
+		Format::Named("Duration"),
+		Format::Named("Result"),
+	]))))));
+	assert_eq!(schema.containers["Duration"], Container::Struct(vec![("secs", Format::U64), ("nanos", Format::U32)]));
+	assert_eq!(schema.containers["Result"], Container::Enum(vec![
+		("Ok", VariantFormat::Newtype(Format::U32)),
+		("Err", VariantFormat::Newtype(Format::Str)),
+	]));
+
+	let list = Schema::trace::<List>().unwrap();
+	assert_eq!(list.containers["List"], Container::Enum(vec![
+		("Nil", VariantFormat::Unit),
+		("Cons", VariantFormat::Tuple(vec![Format::U32, Format::Named("List")])),
+	]));
+
+	// names and struct boundaries aren't encoded
+	let map = Schema::trace::<BTreeMap<String, (u64, u32)>>().unwrap();
+	let pairs = Schema::trace::<Vec<(Vec<u8>, Duration)>>().unwrap();
+	assert!(map.can_read(&map));
+	assert!(pairs.can_read(&map));
+	assert!(!map.can_read(&pairs));
+	assert!(!Schema::trace::<u64>().unwrap().can_read(&Schema::trace::<u32>().unwrap()));
+	assert!(!Schema::trace::<Result<u32, u32>>().unwrap().can_read(&Schema::trace::<Option<u32>>().unwrap()));
+
+	// enums can gain variants, not lose them
+	let mut extended = list.clone();
+	if let Some(Container::Enum(variants)) = extended.containers.get_mut("List") {
+		variants.push(("Empty", VariantFormat::Unit));
+	}
+	assert!(list.can_read(&list));
+	assert!(extended.can_read(&list));
+	assert!(!list.can_read(&extended));
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn transcode() {
+	use std::{collections::BTreeMap, ops::Bound, time::Duration};
+
+	type T = (Vec<Duration>, Option<Result<u32, String>>, BTreeMap<u16, (i64, char)>, Vec<Bound<u8>>);
+	let value: T = (
+		vec![Duration::new(1, 5), Duration::new(0, 0)],
+		Some(Err("nope".into())),
+		vec![(3, (-7, 'é')), (400, (1 << 40, 'x'))].into_iter().collect(),
+		vec![Bound::Included(1), Bound::Unbounded],
+	);
+
+	let mut ser: BytesSer = BytesSer::new();
+	ser.serialize(&value).unwrap();
+	let data = ser.bytes().collect::<Vec<u8>>();
+
+	let transcoder = Transcoder::new::<T>().unwrap();
+	let mut json = Vec::new();
+	let mut slice = &*data;
+	transcoder.to_json(&mut BytesDe::new(&mut slice), &mut json, false).unwrap();
+	let json = String::from_utf8(json).unwrap();
+	assert_eq!(json, concat!(
+		r#"[[{"secs":1,"nanos":5},{"secs":0,"nanos":0}],{"Err":"nope"},"#,
+		r#"{"3":[-7,"é"],"400":[1099511627776,"x"]},[{"Included":1},"Unbounded"]]"#,
+	));
+	assert_eq!(json, serde_json::to_string(&value).unwrap());
+	assert!(slice.is_empty());
+
+	let mut back: BytesSer = BytesSer::new();
+	transcoder.from_json(&json, &mut back).unwrap();
+	assert_eq!(back.bytes().collect::<Vec<u8>>(), data);
+
+	// fields in any order, missing options
+	let json = r#"[[{"nanos": 5, "secs": 1}], null, {"3": [-7, "é"]}, ["Unbounded"]]"#;
+	let mut fixture: BytesSer = BytesSer::new();
+	transcoder.from_json(json, &mut fixture).unwrap();
+	let data = fixture.bytes().collect::<Vec<u8>>();
+	let value = BytesDe::new(&mut &*data).deserialize::<T>().unwrap();
+	assert_eq!(value.0, [Duration::new(1, 5)]);
+	assert_eq!(value.1, None);
+	assert_eq!(value.2[&3], (-7, 'é'));
+
+	assert!(transcoder.from_json(r#"[[{"secs": 1}], null, {}, []]"#, &mut fixture).is_err());
+}
+
+#[test]
+fn serialize_par() {
+	// a length only known once the elements are written, so that chunks have spliced ranges
+	struct Evens(u32);
+
+	impl serde::Serialize for Evens {
+		fn serialize<S: serde::Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+			ser.collect_seq((0 .. self.0).filter(|n| n % 2 == 0))
+		}
+	}
+
+	let items = (0 .. 5000).map(|n| (n, format!("{}", n), Evens(n % 7))).collect::<Vec<_>>();
+
+	let mut ser: BytesSer = BytesSer::new();
+	ser.serialize(&items).unwrap();
+	let expected = ser.bytes().collect::<Vec<u8>>();
+
+	for &threads in &[1, 3, 8, 10000] {
+		let mut par: BytesSer = BytesSer::new();
+		par.serialize(&1u8).unwrap();
+		par.serialize_par(&items, threads).unwrap();
+		par.serialize(&Evens(5)).unwrap();
+		let data = par.bytes().collect::<Vec<u8>>();
+		assert_eq!(data[0], 1);
+		assert_eq!(data[1 .. data.len() - 4], expected[..]);
+		assert_eq!(data[data.len() - 4 ..], [3, 0, 2, 4]);
+	}
+
+	let mut par: BytesSer = BytesSer::new();
+	par.serialize_par::<u8>(&[], 4).unwrap();
+	assert_eq!(par.bytes().collect::<Vec<u8>>(), [0]);
+}
+
+#[test]
+fn pod() {
+	use crate::cvec::CVec;
+
+	let floats = (0 .. 1000).map(|n| n as f32 * 0.5).collect::<Vec<f32>>();
+	let mut ser: BytesSer = BytesSer::new();
+	ser.serialize(&Pod(&floats[..])).unwrap();
+	ser.serialize(&Pod([1u64, u64::max_value(), 3])).unwrap();
+	ser.serialize(&Pod(CVec::from_copy(&[-1i16, 2]))).unwrap();
+	let data = ser.bytes().collect::<Vec<u8>>();
+	assert_eq!(data.len(), 2 + 4000 + 1 + 24 + 1 + 4);
+	assert_eq!(data[6 .. 10], 0.5f32.to_le_bytes());
+
+	let mut slice = &*data;
+	let mut de = BytesDe::new(&mut slice);
+	assert_eq!(de.deserialize::<Pod<CVec<f32>>>().unwrap()[..], floats[..]);
+	assert_eq!(de.deserialize::<Pod<[u64; 3]>>().unwrap().0, [1, u64::max_value(), 3]);
+	assert_eq!(de.deserialize::<Pod<Vec<i16>>>().unwrap().0, [-1, 2]);
+	assert!(slice.is_empty());
+
+	// through a buffered reader, and skipped
+	let mut reader = std::io::BufReader::with_capacity(64, &*data);
+	let mut de = BytesDe::new(&mut reader);
+	assert_eq!(de.deserialize::<Pod<Box<[f32]>>>().unwrap()[..], floats[..]);
+	assert_eq!(de.validate::<Pod<[u64; 3]>>().unwrap(), 25);
+	assert_eq!(de.skip::<Pod<Vec<i16>>>().unwrap(), 5);
+
+	// the bytes have to fit the elements
+	assert!(BytesDe::new(&mut &data[2 + 4000 ..]).deserialize::<Pod<[u64; 2]>>().is_err());
+	assert!(BytesDe::new(&mut &data[2 + 4000 ..]).deserialize::<Pod<Vec<u128>>>().is_err());
+	assert!(BytesDe::new(&mut &data[2 + 4000 ..]).deserialize::<Pod<Vec<u32>>>().unwrap().len() == 6);
+	assert!(matches!(BytesDe::new(&mut &[3u8, 0, 0, 0][..]).validate::<Pod<Vec<u32>>>(), Err(Error::Custom(_))));
+	assert!(matches!(BytesDe::new(&mut &[3u8, 0, 0, 0][..]).skip::<Pod<Vec<u32>>>(), Err(Error::Custom(_))));
+}
+
+#[cfg(all(test, target_pointer_width = "64"))]
+fn hex(data: &[u8]) -> String {
+	data.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+fn unhex(hex: &str) -> Vec<u8> {
+	hex.split_whitespace().map(|byte| u8::from_str_radix(byte, 16).unwrap()).collect()
+}
+
+// checks the encodings against `golden.txt`, which must only ever change along
+// with a format version bump; `UTL_BLESS_GOLDEN=1` rewrites it; the `len` cases
+// go up to `usize::MAX`, so it only runs where that is 64 bits
+#[cfg(target_pointer_width = "64")]
+#[test]
+fn golden() {
+	use {
+		serde::{Serialize, de::DeserializeOwned},
+		std::{
+			collections::{BTreeMap, BTreeSet},
+			fmt::Debug, marker::PhantomData, ops::Bound, time::Duration,
+		},
+	};
+
+	let mut lines = Vec::new();
+
+	let mut case = |label: &str, value: &dyn Fn(&mut BytesSer) -> Vec<u8>| {
+		let mut ser = BytesSer::new();
+		lines.push(format!("{} = {}", label, hex(&value(&mut ser))));
+	};
+
+	fn check<T: Serialize + DeserializeOwned + PartialEq + Debug>(value: &T, ser: &mut BytesSer) -> Vec<u8> {
+		ser.serialize(value).unwrap();
+		let data = ser.bytes().collect::<Vec<u8>>();
+		let mut slice = &*data;
+		assert_eq!(BytesDe::new(&mut slice).deserialize::<T>().unwrap(), *value);
+		assert!(slice.is_empty());
+		data
+	}
+
+	macro_rules! cases {
+		($($t:ty: $($value:expr),*;)*) => {$($(
+			let value: $t = $value;
+			case(&format!("{} {:?}", stringify!($t), value), &|ser| check(&value, ser));
+		)*)*};
+	}
+
+	// every bit length, one below and at each power of two
+	macro_rules! powers {
+		($t:ty) => {
+			(0 .. <$t>::BITS)
+				.flat_map(|k| [(1 << k) - 1, 1 << k])
+				.chain(Some(<$t>::MAX))
+				.collect::<BTreeSet<$t>>()
+		};
+	}
+
+	macro_rules! varints {
+		($($t:ty)*) => {$(
+			for value in powers!($t) {
+				cases!($t: value;);
+			}
+		)*};
+	}
+
+	cases! {
+		bool: false, true;
+		u8: 0, 1, 0x7F, 0x80, 0xFF;
+		i8: 0, 1, -1, i8::MIN, i8::MAX;
+	}
+
+	varints!(u16 u32 u64 u128);
+
+	for value in powers!(usize) {
+		case(&format!("len {}", value), &|ser| {
+			ser.ser_usize(value);
+			let data = ser.bytes().collect::<Vec<u8>>();
+			let mut slice = &*data;
+			assert_eq!(BytesDe::new(&mut slice).de_usize().unwrap(), value);
+			assert!(slice.is_empty());
+			data
+		});
+	}
+
+	cases! {
+		i16: 0, 1, -1, 63, -64, 64, -65, i16::MIN, i16::MAX;
+		i32: 0, 1, -1, 63, -64, 64, -65, i32::MIN, i32::MAX;
+		i64: 0, 1, -1, 63, -64, 64, -65, i64::MIN, i64::MAX;
+		i128: 0, 1, -1, 63, -64, 64, -65, i128::MIN, i128::MAX;
+		f32: 0., -0., 1.5, -2.25, f32::INFINITY, f32::MIN_POSITIVE, f32::MAX;
+		f64: 0., -0., 1.5, -2.25, f64::NEG_INFINITY, f64::MIN_POSITIVE, f64::MAX;
+		char: 'a', '\u{7F}', '\u{80}', 'é', '€', '\u{FFFF}', '😀';
+		String: "".into(), "a".into(), "héllo".into(), "x".repeat(128);
+		(): ();
+		PhantomData<u8>: PhantomData;
+		Option<u8>: None, Some(0);
+		Option<Option<u32>>: Some(None), Some(Some(300));
+		Result<u8, String>: Ok(5), Err("no".into());
+		Bound<u16>: Bound::Unbounded, Bound::Included(300), Bound::Excluded(0);
+		Vec<u8>: vec![], vec![1, 2, 3];
+		Vec<u16>: vec![1, 300, u16::MAX];
+		Vec<Vec<String>>: vec![vec![], vec!["a".into(), "bc".into()]];
+		(u8, u32, String): (1, 1 << 20, "t".into());
+		[u32; 3]: [7, 1 << 8, 1 << 31];
+		BTreeMap<u8, String>: BTreeMap::new(), vec![(1, "a".into()), (2, "bc".into())].into_iter().collect();
+		Duration: Duration::new(1, 500), Duration::new(1 << 40, 999_999_999);
+	}
+
+	let actual = lines.iter().map(|line| format!("{}\n", line)).collect::<String>();
+
+	if std::env::var_os("UTL_BLESS_GOLDEN").is_some() {
+		std::fs::write(concat!(env!("CARGO_MANIFEST_DIR"), "/src/bytes/golden.txt"), &actual).unwrap();
+		return;
+	}
+
+	assert_eq!(actual, include_str!("golden.txt"));
+}
+
+#[test]
+fn malformed() {
+	use {crate::cvec::CVec, std::io::ErrorKind::UnexpectedEof};
+
+	macro_rules! malformed {
+		($($hex:literal as $t:ty => $pat:pat $(if $guard:expr)?,)*) => {$(
+			let data = unhex($hex);
+			let result = BytesDe::with_limits(&mut &*data, 64, 4).deserialize::<$t>();
+			assert!(
+				matches!(result, Err($pat) $(if $guard)?),
+				"{} as {}: {:?}", $hex, stringify!($t), result,
+			);
+		)*};
+	}
+
+	malformed! {
+		"02" as bool => Error::InvalidBool(2),
+		"03 07" as Option<u8> => Error::InvalidBool(3),
+		"" as u8 => Error::Io(ref e) if e.kind() == UnexpectedEof,
+		"80" as u16 => Error::Io(ref e) if e.kind() == UnexpectedEof,
+		"c0" as u32 => Error::Io(ref e) if e.kind() == UnexpectedEof,
+		"ff ff" as u64 => Error::Io(ref e) if e.kind() == UnexpectedEof,
+		"f0 00" as u128 => Error::Io(ref e) if e.kind() == UnexpectedEof,
+		"ff" as usize => Error::Io(ref e) if e.kind() == UnexpectedEof,
+		"ff ff ff ff ff ff ff ff ff ff ff ff" as Vec<u8> => Error::VarintOverflow,
+		"ff ff ff ff ff ff ff ff ff ff ff ff" as String => Error::VarintOverflow,
+		"ff fe fe fe fe fe fe fe fe 01" as Vec<u8> => Error::VarintOverflow,
+		"03 61 62" as String => Error::Io(ref e) if e.kind() == UnexpectedEof,
+		"02 ff fe" as String => Error::Utf8(_),
+		"80" as char => Error::Utf8(_),
+		"fe" as char => Error::Utf8(_),
+		"ff" as char => Error::Utf8(_),
+		"f8 88 80 80 80" as char => Error::Utf8(_),
+		"e2 82" as char => Error::Io(ref e) if e.kind() == UnexpectedEof,
+		"41" as String => Error::AllocExceeded,
+		"ff ff ff 7f" as Vec<u8> => Error::AllocExceeded,
+		"ff ff ff 7f" as CVec<u64> => Error::AllocExceeded,
+		"01 01 01 01 01 00" as Vec<Vec<Vec<Vec<Vec<u8>>>>> => Error::DepthExceeded,
+		"01 01 01 01 01 00" as Option<Option<Option<Option<Option<u8>>>>> => Error::DepthExceeded,
+		"02 00" as Result<u8, u8> => Error::Custom(_),
+		"03 00 00 00" as Pod<Vec<u32>> => Error::Custom(_),
+	}
+}
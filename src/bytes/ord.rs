@@ -0,0 +1,678 @@
+use {
+	super::{ser, de::{self, eof}},
+	crate::vec_ext::VecExt,
+	serde::{
+		Serialize,
+		Deserializer,
+		de::{Visitor, DeserializeSeed, IntoDeserializer},
+	},
+	std::borrow::Cow,
+};
+
+// an encoding whose bytes compare (with `memcmp`) like the values they encode
+// compare with `Ord`; floats compare like `total_cmp`
+//
+// integers are fixed-size big-endian, with the sign bit flipped if signed;
+// strings and bytes escape 0x00 as 0x00 0xFF and end with 0x00 0x00;
+// sequences and maps put 0x01 before each element and end with 0x00;
+// options, enums and tuples are like in `BytesSer` but with fixed-size indices
+
+#[derive(Debug, Clone, Default)]
+pub struct OrdSer<B = crate::cvec::CVec<u8>> { buffer: B }
+
+pub struct OrdDe<'de> { input: &'de [u8] }
+
+pub struct OrdDeLen<'a, 'de> { len: usize, de: &'a mut OrdDe<'de> }
+
+impl<B: VecExt<u8>> OrdSer<B> {
+	pub fn new() -> Self where B: Default { Self::default() }
+
+	pub fn reuse_vec(mut buffer: B) -> Self {
+		buffer.clear();
+		Self { buffer }
+	}
+
+	pub fn len(&self) -> usize { self.buffer.len() }
+
+	pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+	pub fn bytes(&self) -> &[u8] { &self.buffer }
+
+	pub fn clear(&mut self) { self.buffer.clear(); }
+
+	pub fn into_inner(self) -> B { self.buffer }
+
+	pub fn serialize<T: Serialize>(&mut self, value: &T) -> ser::Result {
+		value.serialize(self)
+	}
+
+	fn ecs(&mut self, s: &[u8]) { self.buffer.extend_copy_slice(s); }
+
+	fn e1(&mut self, b: u8) { self.buffer.extend_one(b); }
+
+	fn ser_escaped(&mut self, mut v: &[u8]) {
+		while let Some(zero) = v.iter().position(|&b| b == 0) {
+			self.ecs(&v[..zero]);
+			self.ecs(&[0x00, 0xFF]);
+			v = &v[zero + 1 ..];
+		}
+		self.ecs(v);
+		self.ecs(&[0x00, 0x00]);
+	}
+}
+
+macro_rules! ser_ints {
+	($($ser:ident $ty:ty, $signed_ser:ident $signed_ty:ty;)*) => {
+		$(
+			fn $ser(self, v: $ty) -> ser::Result {
+				self.ecs(&v.to_be_bytes());
+				Ok(())
+			}
+
+			fn $signed_ser(self, v: $signed_ty) -> ser::Result {
+				self.ecs(&((v as $ty) ^ (1 << (<$ty>::BITS - 1))).to_be_bytes());
+				Ok(())
+			}
+		)*
+	};
+}
+
+macro_rules! ser_floats {
+	($($ser:ident $ty:ty, $bits:ty;)*) => {
+		$(
+			fn $ser(self, v: $ty) -> ser::Result {
+				let bits = v.to_bits();
+				let sign = 1 << (<$bits>::BITS - 1);
+				self.ecs(&(if bits & sign == 0 { bits | sign } else { !bits }).to_be_bytes());
+				Ok(())
+			}
+		)*
+	};
+}
+
+impl<'a, B: VecExt<u8>> serde::Serializer for &'a mut OrdSer<B> {
+	type Ok = ();
+	type Error = ser::Error;
+
+	type SerializeSeq = Self;
+	type SerializeTuple = Self;
+	type SerializeTupleStruct = Self;
+	type SerializeTupleVariant = Self;
+	type SerializeMap = Self;
+	type SerializeStruct = Self;
+	type SerializeStructVariant = Self;
+
+	fn serialize_bool(self, v: bool) -> ser::Result { self.e1(v as _); Ok(()) }
+
+	ser_ints!{
+		serialize_u8   u8  , serialize_i8   i8  ;
+		serialize_u16  u16 , serialize_i16  i16 ;
+		serialize_u32  u32 , serialize_i32  i32 ;
+		serialize_u64  u64 , serialize_i64  i64 ;
+		serialize_u128 u128, serialize_i128 i128;
+	}
+
+	ser_floats!{
+		serialize_f32 f32, u32;
+		serialize_f64 f64, u64;
+	}
+
+	fn serialize_char(self, v: char) -> ser::Result { self.serialize_u32(v as _) }
+
+	fn serialize_str(self, v: &str) -> ser::Result {
+		self.ser_escaped(v.as_bytes());
+		Ok(())
+	}
+
+	fn serialize_bytes(self, v: &[u8]) -> ser::Result {
+		self.ser_escaped(v);
+		Ok(())
+	}
+
+	fn serialize_none(self) -> ser::Result { self.e1(0); Ok(()) }
+
+	fn serialize_some<U: ?Sized + Serialize>(self, v: &U) -> ser::Result {
+		self.e1(1);
+		v.serialize(self)
+	}
+
+	fn serialize_unit(self) -> ser::Result { Ok(()) }
+
+	fn serialize_unit_struct(self, _name: &'static str) -> ser::Result { Ok(()) }
+
+	fn serialize_unit_variant(
+		self, _name: &'static str, variant_index: u32, _variant: &'static str,
+	) -> ser::Result {
+		self.serialize_u32(variant_index)
+	}
+
+	fn serialize_newtype_struct<U: ?Sized + Serialize>(
+		self, _name: &'static str, value: &U,
+	) -> ser::Result {
+		value.serialize(self)
+	}
+
+	fn serialize_newtype_variant<U: ?Sized + Serialize>(
+		self,
+		_name: &'static str, variant_index: u32, _variant: &'static str, value: &U,
+	) -> ser::Result {
+		self.ecs(&variant_index.to_be_bytes());
+		value.serialize(self)
+	}
+
+	fn serialize_seq(self, _len: Option<usize>) -> ser::Result<Self> { Ok(self) }
+
+	fn serialize_tuple(self, _len: usize) -> ser::Result<Self> { Ok(self) }
+
+	fn serialize_tuple_struct(self, _: &'static str, _: usize) -> ser::Result<Self> {
+		Ok(self)
+	}
+
+	fn serialize_tuple_variant(
+		self,
+		_name: &'static str,
+		variant_index: u32,
+		_variant: &'static str,
+		_len: usize,
+	) -> ser::Result<Self> {
+		self.ecs(&variant_index.to_be_bytes());
+		Ok(self)
+	}
+
+	fn serialize_map(self, _len: Option<usize>) -> ser::Result<Self> { Ok(self) }
+
+	fn serialize_struct(self, _name: &'static str, _len: usize) -> ser::Result<Self> {
+		Ok(self)
+	}
+
+	fn serialize_struct_variant(
+		self,
+		_name: &'static str,
+		variant_index: u32,
+		_variant: &'static str,
+		_len: usize,
+	) -> ser::Result<Self> {
+		self.ecs(&variant_index.to_be_bytes());
+		Ok(self)
+	}
+}
+
+impl<B: VecExt<u8>> serde::ser::SerializeSeq for &'_ mut OrdSer<B> {
+	type Ok = ();
+	type Error = ser::Error;
+
+	fn serialize_element<U: ?Sized + Serialize>(&mut self, value: &U) -> ser::Result {
+		self.e1(1);
+		value.serialize(&mut **self)
+	}
+
+	fn end(self) -> ser::Result { self.e1(0); Ok(()) }
+}
+
+impl<B: VecExt<u8>> serde::ser::SerializeTuple for &'_ mut OrdSer<B> {
+	type Ok = ();
+	type Error = ser::Error;
+
+	fn serialize_element<U: ?Sized + Serialize>(&mut self, value: &U) -> ser::Result {
+		value.serialize(&mut **self)
+	}
+
+	fn end(self) -> ser::Result { Ok(()) }
+}
+
+impl<B: VecExt<u8>> serde::ser::SerializeTupleStruct for &'_ mut OrdSer<B> {
+	type Ok = ();
+	type Error = ser::Error;
+
+	fn serialize_field<U: ?Sized + Serialize>(&mut self, value: &U) -> ser::Result {
+		value.serialize(&mut **self)
+	}
+
+	fn end(self) -> ser::Result { Ok(()) }
+}
+
+impl<B: VecExt<u8>> serde::ser::SerializeTupleVariant for &'_ mut OrdSer<B> {
+	type Ok = ();
+	type Error = ser::Error;
+
+	fn serialize_field<U: ?Sized + Serialize>(&mut self, value: &U) -> ser::Result {
+		value.serialize(&mut **self)
+	}
+
+	fn end(self) -> ser::Result { Ok(()) }
+}
+
+impl<B: VecExt<u8>> serde::ser::SerializeMap for &'_ mut OrdSer<B> {
+	type Ok = ();
+	type Error = ser::Error;
+
+	fn serialize_key<U: ?Sized + Serialize>(&mut self, key: &U) -> ser::Result {
+		self.e1(1);
+		key.serialize(&mut **self)
+	}
+
+	fn serialize_value<U: ?Sized + Serialize>(&mut self, value: &U) -> ser::Result {
+		value.serialize(&mut **self)
+	}
+
+	fn end(self) -> ser::Result { self.e1(0); Ok(()) }
+}
+
+impl<B: VecExt<u8>> serde::ser::SerializeStruct for &'_ mut OrdSer<B> {
+	type Ok = ();
+	type Error = ser::Error;
+
+	fn serialize_field<U: ?Sized + Serialize>(
+		&mut self, _key: &'static str, value: &U,
+	) -> ser::Result {
+		value.serialize(&mut **self)
+	}
+
+	fn end(self) -> ser::Result { Ok(()) }
+}
+
+impl<B: VecExt<u8>> serde::ser::SerializeStructVariant for &'_ mut OrdSer<B> {
+	type Ok = ();
+	type Error = ser::Error;
+
+	fn serialize_field<U: ?Sized + Serialize>(
+		&mut self, _key: &'static str, value: &U,
+	) -> ser::Result {
+		value.serialize(&mut **self)
+	}
+
+	fn end(self) -> ser::Result { Ok(()) }
+}
+
+impl<'de> OrdDe<'de> {
+	pub fn new(input: &'de [u8]) -> Self { Self { input } }
+
+	pub fn remaining(&self) -> &'de [u8] { self.input }
+
+	pub fn deserialize<T: serde::Deserialize<'de>>(&mut self) -> de::Result<T> {
+		T::deserialize(self)
+	}
+
+	fn take(&mut self, n: usize) -> de::Result<&'de [u8]> {
+		if n > self.input.len() { return eof(); }
+		let (taken, rest) = self.input.split_at(n);
+		self.input = rest;
+		Ok(taken)
+	}
+
+	fn array<const N: usize>(&mut self) -> de::Result<[u8; N]> {
+		let mut bytes = [0u8; N];
+		bytes.copy_from_slice(self.take(N)?);
+		Ok(bytes)
+	}
+
+	fn de_bool(&mut self) -> de::Result<bool> {
+		match self.array::<1>()? {
+			[0] => Ok(false),
+			[1] => Ok(true),
+			[n] => Err(de::Error::InvalidBool(n)),
+		}
+	}
+
+	fn de_escaped(&mut self) -> de::Result<Cow<'de, [u8]>> {
+		let input = self.input;
+		let mut owned = None::<Vec<u8>>;
+		let mut pos = 0;
+
+		loop {
+			let zero = match input[pos..].iter().position(|&b| b == 0) {
+				Some(zero) => pos + zero,
+				None => return eof(),
+			};
+
+			let escape = *input.get(zero + 1).map_or_else(eof, Ok)?;
+
+			if escape == 0x00 {
+				self.input = &input[zero + 2 ..];
+				return Ok(match owned {
+					None => Cow::Borrowed(&input[..zero]),
+					Some(mut owned) => {
+						owned.extend_from_slice(&input[pos .. zero]);
+						Cow::Owned(owned)
+					},
+				});
+			} else if escape == 0xFF {
+				owned.get_or_insert_with(Vec::new).extend_from_slice(&input[pos ..= zero]);
+				pos = zero + 2;
+			} else {
+				return Err(de::Error::Custom(
+					format!("invalid escape 0x00 0x{:02X} in ordered bytes", escape)
+				));
+			}
+		}
+	}
+}
+
+macro_rules! de_ints {
+	($($de:ident $visit:ident $ty:ty, $signed_de:ident $signed_visit:ident $signed_ty:ty;)*) => {
+		$(
+			fn $de<V: Visitor<'de>>(self, v: V) -> de::Result<V::Value> {
+				v.$visit(<$ty>::from_be_bytes(self.array()?))
+			}
+
+			fn $signed_de<V: Visitor<'de>>(self, v: V) -> de::Result<V::Value> {
+				let n = <$ty>::from_be_bytes(self.array()?) ^ (1 << (<$ty>::BITS - 1));
+				v.$signed_visit(n as $signed_ty)
+			}
+		)*
+	};
+}
+
+macro_rules! de_floats {
+	($($de:ident $visit:ident $ty:ty, $bits:ty;)*) => {
+		$(
+			fn $de<V: Visitor<'de>>(self, v: V) -> de::Result<V::Value> {
+				let bits = <$bits>::from_be_bytes(self.array()?);
+				let sign = 1 << (<$bits>::BITS - 1);
+				v.$visit(<$ty>::from_bits(if bits & sign != 0 { bits ^ sign } else { !bits }))
+			}
+		)*
+	};
+}
+
+impl<'a, 'de> Deserializer<'de> for &'a mut OrdDe<'de> {
+	type Error = de::Error;
+
+	fn deserialize_bool<V: Visitor<'de>>(self, v: V) -> de::Result<V::Value> {
+		v.visit_bool(self.de_bool()?)
+	}
+
+	de_ints!{
+		deserialize_u8   visit_u8   u8  , deserialize_i8   visit_i8   i8  ;
+		deserialize_u16  visit_u16  u16 , deserialize_i16  visit_i16  i16 ;
+		deserialize_u32  visit_u32  u32 , deserialize_i32  visit_i32  i32 ;
+		deserialize_u64  visit_u64  u64 , deserialize_i64  visit_i64  i64 ;
+		deserialize_u128 visit_u128 u128, deserialize_i128 visit_i128 i128;
+	}
+
+	de_floats!{
+		deserialize_f32 visit_f32 f32, u32;
+		deserialize_f64 visit_f64 f64, u64;
+	}
+
+	fn deserialize_char<V: Visitor<'de>>(self, v: V) -> de::Result<V::Value> {
+		let n = u32::from_be_bytes(self.array()?);
+		v.visit_char(std::char::from_u32(n).ok_or_else(|| de::Error::Custom(
+			format!("0x{:X} isn't a char", n)
+		))?)
+	}
+
+	fn deserialize_str<V: Visitor<'de>>(self, v: V) -> de::Result<V::Value> {
+		match self.de_escaped()? {
+			Cow::Borrowed(bytes) =>
+				v.visit_borrowed_str(std::str::from_utf8(bytes).map_err(de::Error::Utf8)?),
+			Cow::Owned(bytes) => v.visit_string(
+				String::from_utf8(bytes).map_err(|e| de::Error::Utf8(e.utf8_error()))?
+			),
+		}
+	}
+
+	fn deserialize_string<V: Visitor<'de>>(self, v: V) -> de::Result<V::Value> {
+		self.deserialize_str(v)
+	}
+
+	fn deserialize_bytes<V: Visitor<'de>>(self, v: V) -> de::Result<V::Value> {
+		match self.de_escaped()? {
+			Cow::Borrowed(bytes) => v.visit_borrowed_bytes(bytes),
+			Cow::Owned(bytes) => v.visit_byte_buf(bytes),
+		}
+	}
+
+	fn deserialize_byte_buf<V: Visitor<'de>>(self, v: V) -> de::Result<V::Value> {
+		self.deserialize_bytes(v)
+	}
+
+	fn deserialize_option<V: Visitor<'de>>(self, v: V) -> de::Result<V::Value> {
+		if self.de_bool()? {
+			v.visit_some(self)
+		} else {
+			v.visit_none()
+		}
+	}
+
+	fn deserialize_unit<V: Visitor<'de>>(self, v: V) -> de::Result<V::Value> {
+		v.visit_unit()
+	}
+
+	fn deserialize_unit_struct<V: Visitor<'de>>(
+		self, _name: &'static str, v: V,
+	) -> de::Result<V::Value> {
+		self.deserialize_unit(v)
+	}
+
+	fn deserialize_newtype_struct<V: Visitor<'de>>(
+		self, _name: &'static str, v: V,
+	) -> de::Result<V::Value> {
+		v.visit_newtype_struct(self)
+	}
+
+	fn deserialize_seq<V: Visitor<'de>>(self, v: V) -> de::Result<V::Value> {
+		v.visit_seq(OrdDeLen { len: usize::max_value(), de: self })
+	}
+
+	fn deserialize_tuple<V: Visitor<'de>>(
+		self, len: usize, v: V,
+	) -> de::Result<V::Value> {
+		v.visit_seq(OrdDeLen { len, de: self })
+	}
+
+	fn deserialize_tuple_struct<V: Visitor<'de>>(
+		self, _name: &'static str, len: usize, v: V,
+	) -> de::Result<V::Value> {
+		v.visit_seq(OrdDeLen { len, de: self })
+	}
+
+	fn deserialize_map<V: Visitor<'de>>(self, v: V) -> de::Result<V::Value> {
+		v.visit_map(OrdDeLen { len: usize::max_value(), de: self })
+	}
+
+	fn deserialize_struct<V: Visitor<'de>>(
+		self, _name: &'static str, fields: &'static [&'static str], v: V,
+	) -> de::Result<V::Value> {
+		v.visit_seq(OrdDeLen { len: fields.len(), de: self })
+	}
+
+	fn deserialize_enum<V: Visitor<'de>>(
+		self, _name: &'static str, _variants: &'static [&'static str], v: V,
+	) -> de::Result<V::Value> {
+		v.visit_enum(self)
+	}
+
+	fn deserialize_identifier<V: Visitor<'de>>(self, _: V) -> de::Result<V::Value> {
+		Err(de::Error::Custom("the ordered encoding isn't self-describing".into()))
+	}
+
+	fn deserialize_ignored_any<V: Visitor<'de>>(self, _: V) -> de::Result<V::Value> {
+		Err(de::Error::Custom("the ordered encoding isn't self-describing".into()))
+	}
+
+	fn deserialize_any<V: Visitor<'de>>(self, _: V) -> de::Result<V::Value> {
+		Err(de::Error::Custom("the ordered encoding isn't self-describing".into()))
+	}
+
+	fn is_human_readable(&self) -> bool { false }
+}
+
+// `len` is `usize::max_value()` for sequences and maps, which are terminated
+// by a marker rather than prefixed by their length
+impl<'a, 'de> OrdDeLen<'a, 'de> {
+	fn next(&mut self) -> de::Result<bool> {
+		if self.len == usize::max_value() {
+			self.de.de_bool()
+		} else if self.len == 0 {
+			Ok(false)
+		} else {
+			self.len -= 1;
+			Ok(true)
+		}
+	}
+}
+
+impl<'a, 'de> serde::de::SeqAccess<'de> for OrdDeLen<'a, 'de> {
+	type Error = de::Error;
+
+	fn next_element_seed<T>(&mut self, seed: T) -> de::Result<Option<T::Value>> where
+		T: DeserializeSeed<'de>,
+	{
+		Ok(if self.next()? { Some(seed.deserialize(&mut *self.de)?) } else { None })
+	}
+
+	fn size_hint(&self) -> Option<usize> {
+		if self.len == usize::max_value() { None } else { Some(self.len) }
+	}
+}
+
+impl<'a, 'de> serde::de::MapAccess<'de> for OrdDeLen<'a, 'de> {
+	type Error = de::Error;
+
+	fn next_key_seed<T>(&mut self, seed: T) -> de::Result<Option<T::Value>> where
+		T: DeserializeSeed<'de>,
+	{
+		Ok(if self.next()? { Some(seed.deserialize(&mut *self.de)?) } else { None })
+	}
+
+	fn next_value_seed<T>(&mut self, seed: T) -> de::Result<T::Value> where
+		T: DeserializeSeed<'de>,
+	{
+		seed.deserialize(&mut *self.de)
+	}
+}
+
+impl<'a, 'de> serde::de::EnumAccess<'de> for &'a mut OrdDe<'de> {
+	type Error = de::Error;
+	type Variant = Self;
+
+	fn variant_seed<V>(self, seed: V) -> de::Result<(V::Value, Self)> where
+		V: DeserializeSeed<'de>,
+	{
+		let idx = u32::from_be_bytes(self.array()?);
+		Ok((seed.deserialize(idx.into_deserializer())?, self))
+	}
+}
+
+impl<'a, 'de> serde::de::VariantAccess<'de> for &'a mut OrdDe<'de> {
+	type Error = de::Error;
+
+	fn unit_variant(self) -> de::Result { Ok(()) }
+
+	fn newtype_variant_seed<V>(self, seed: V) -> de::Result<V::Value> where
+		V: DeserializeSeed<'de>,
+	{
+		seed.deserialize(self)
+	}
+
+	fn tuple_variant<V>(self, len: usize, seed: V) -> de::Result<V::Value> where
+		V: Visitor<'de>,
+	{
+		self.deserialize_tuple(len, seed)
+	}
+
+	fn struct_variant<V>(self, fields: &'static [&'static str], seed: V) ->
+		de::Result<V::Value>
+	where
+		V: Visitor<'de>,
+	{
+		self.deserialize_tuple(fields.len(), seed)
+	}
+}
+
+#[test]
+fn test() {
+	use {rand::prelude::*, std::{cmp::Ordering, fmt::Debug}};
+
+	fn enc(v: &impl Serialize) -> Vec<u8> {
+		let mut ser: OrdSer = OrdSer::new();
+		ser.serialize(v).unwrap();
+		ser.bytes().to_vec()
+	}
+
+	fn ck<T>(a: T, b: T, cmp: impl Fn(&T, &T) -> Ordering) where
+		T: Serialize + for<'de> serde::Deserialize<'de> + Debug,
+	{
+		let (ea, eb) = (enc(&a), enc(&b));
+		assert_eq!(cmp(&a, &b), ea.cmp(&eb), "{:?} {:?} → {:?} {:?}", a, b, ea, eb);
+		for (v, e) in [(&a, &ea), (&b, &eb)] {
+			let mut de = OrdDe::new(e);
+			let w = de.deserialize::<T>().unwrap();
+			assert_eq!(cmp(v, &w), Ordering::Equal, "{:?} → {:?} → {:?}", v, e, w);
+			assert!(de.remaining().is_empty());
+		}
+	}
+
+	fn ck_ord<T>(a: T, b: T) where
+		T: Serialize + for<'de> serde::Deserialize<'de> + Debug + Ord,
+	{
+		ck(a, b, T::cmp);
+	}
+
+	fn rand_string(rng: &mut impl Rng) -> String {
+		(0..rng.gen_range(0..4))
+			.map(|_| ['\0', '\u{1}', 'a', 'b', '\u{FF}', '\u{10FFFF}'][rng.gen_range(0..6)])
+			.collect()
+	}
+
+	let mut rng = rand::thread_rng();
+
+	let edges = [i64::min_value(), -257, -256, -1, 0, 1, 255, 256, i64::max_value()];
+	for &a in &edges {
+		for &b in &edges {
+			ck_ord(a, b);
+			ck_ord(a as i8, b as i8);
+			ck_ord(a as i128, b as i128);
+			ck_ord(a as u32, b as u32);
+			ck(a as f64, b as f64, f64::total_cmp);
+		}
+	}
+
+	let floats = [
+		f32::NEG_INFINITY, -1.5, -f32::MIN_POSITIVE, -0.0, 0.0,
+		f32::MIN_POSITIVE, 1.5, f32::INFINITY, f32::NAN,
+	];
+	for &a in &floats {
+		for &b in &floats {
+			ck(a, b, f32::total_cmp);
+		}
+	}
+
+	for _ in 0..1 << 12 {
+		ck_ord(rng.gen::<i16>(), rng.gen::<i16>());
+		ck_ord(rng.gen::<u64>() >> rng.gen_range(0..64), rng.gen::<u64>() >> rng.gen_range(0..64));
+		ck_ord(rng.gen::<char>(), rng.gen::<char>());
+		ck_ord(rng.gen::<bool>(), rng.gen::<bool>());
+		ck_ord(rand_string(&mut rng), rand_string(&mut rng));
+		ck_ord(
+			rand_string(&mut rng).into_bytes(),
+			rand_string(&mut rng).into_bytes(),
+		);
+		ck_ord(
+			(rng.gen::<u8>() % 3, rand_string(&mut rng), rng.gen::<i8>() % 3),
+			(rng.gen::<u8>() % 3, rand_string(&mut rng), rng.gen::<i8>() % 3),
+		);
+		ck_ord(
+			rng.gen::<bool>().then(|| rng.gen::<i8>() % 3),
+			rng.gen::<bool>().then(|| rng.gen::<i8>() % 3),
+		);
+		let mut rand_result = || if rng.gen() {
+			Ok::<_, String>(rng.gen::<u8>() % 3)
+		} else {
+			Err(rand_string(&mut rng))
+		};
+		ck_ord(rand_result(), rand_result());
+		let mut rand_vec = || (0..rng.gen_range(0..4))
+			.map(|_| rng.gen::<i16>() % 3)
+			.collect::<Vec<_>>();
+		ck_ord(rand_vec(), rand_vec());
+		let mut rand_map = || (0..rng.gen_range(0..4))
+			.map(|_| (rng.gen::<u8>() % 4, rng.gen::<bool>()))
+			.collect::<std::collections::BTreeMap<_, _>>();
+		ck_ord(rand_map(), rand_map());
+	}
+
+	let e = enc(&(1u8, 2u8));
+	assert!(OrdDe::new(&e).deserialize::<serde::de::IgnoredAny>().is_err());
+}
@@ -0,0 +1,210 @@
+use {
+	super::{BytesDe, Error, de::ReadBuf},
+	crate::cvec::CVec,
+	serde::{
+		Serialize,
+		Serializer,
+		Deserialize,
+		Deserializer,
+		de::{self, Visitor, SeqAccess},
+	},
+	std::{fmt, io::Read, marker::PhantomData, mem, ops::Deref, slice},
+};
+
+// a slice of numbers encoded like a byte buffer of their little-endian bytes,
+// so that it's written and read in one go; the element type isn't encoded,
+// decoding only checks that the bytes make up a whole number of elements
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Pod<C>(pub C);
+
+/// Numbers that a `Pod` reads and writes as their raw bytes.
+///
+/// # Safety
+///
+/// The type must have no padding, so all of its bytes are initialized, and
+/// every bit pattern must be a valid value. `swap_le` must convert between the
+/// native and the little-endian byte order.
+pub unsafe trait PodElem: Copy + Default + 'static {
+	fn swap_le(self) -> Self;
+}
+
+macro_rules! pod_ints {
+	($($t:ty)*) => {$(
+		unsafe impl PodElem for $t {
+			fn swap_le(self) -> Self { self.to_le() }
+		}
+	)*};
+}
+
+pod_ints!(u8 u16 u32 u64 u128 i8 i16 i32 i64 i128);
+
+unsafe impl PodElem for f32 {
+	fn swap_le(self) -> Self { Self::from_bits(self.to_bits().to_le()) }
+}
+
+unsafe impl PodElem for f64 {
+	fn swap_le(self) -> Self { Self::from_bits(self.to_bits().to_le()) }
+}
+
+pub trait PodSlice {
+	type Elem: PodElem;
+	fn pod_slice(&self) -> &[Self::Elem];
+}
+
+// `PodSlice`s that a `Pod` can be decoded into
+pub trait PodBuf: PodSlice + Sized {
+	// `None` if the container can't hold `len` elements
+	fn zeroed(len: usize) -> Option<Self>;
+	fn pod_slice_mut(&mut self) -> &mut [Self::Elem];
+}
+
+impl<T: PodElem> PodSlice for [T] {
+	type Elem = T;
+	fn pod_slice(&self) -> &[T] { self }
+}
+
+impl<T: PodElem, const N: usize> PodSlice for [T; N] {
+	type Elem = T;
+	fn pod_slice(&self) -> &[T] { self }
+}
+
+impl<S: ?Sized + PodSlice> PodSlice for &S {
+	type Elem = S::Elem;
+	fn pod_slice(&self) -> &[S::Elem] { (**self).pod_slice() }
+}
+
+impl<T: PodElem> PodSlice for Vec<T> {
+	type Elem = T;
+	fn pod_slice(&self) -> &[T] { self }
+}
+
+impl<T: PodElem> PodSlice for CVec<T> {
+	type Elem = T;
+	fn pod_slice(&self) -> &[T] { self }
+}
+
+impl<T: PodElem> PodSlice for Box<[T]> {
+	type Elem = T;
+	fn pod_slice(&self) -> &[T] { self }
+}
+
+impl<T: PodElem, const N: usize> PodBuf for [T; N] {
+	fn zeroed(len: usize) -> Option<Self> { (len == N).then(|| [T::default(); N]) }
+	fn pod_slice_mut(&mut self) -> &mut [T] { self }
+}
+
+impl<T: PodElem> PodBuf for Vec<T> {
+	fn zeroed(len: usize) -> Option<Self> { Some(vec![T::default(); len]) }
+	fn pod_slice_mut(&mut self) -> &mut [T] { self }
+}
+
+impl<T: PodElem> PodBuf for CVec<T> {
+	fn zeroed(len: usize) -> Option<Self> {
+		let mut vec = CVec::new();
+		vec.resize(len, T::default());
+		Some(vec)
+	}
+	fn pod_slice_mut(&mut self) -> &mut [T] { self }
+}
+
+impl<T: PodElem> PodBuf for Box<[T]> {
+	fn zeroed(len: usize) -> Option<Self> { Some(vec![T::default(); len].into_boxed_slice()) }
+	fn pod_slice_mut(&mut self) -> &mut [T] { self }
+}
+
+fn bytes<T: PodElem>(slice: &[T]) -> &[u8] {
+	unsafe { slice::from_raw_parts(slice.as_ptr() as *const u8, mem::size_of_val(slice)) }
+}
+
+fn bytes_mut<T: PodElem>(slice: &mut [T]) -> &mut [u8] {
+	unsafe { slice::from_raw_parts_mut(slice.as_mut_ptr() as *mut u8, mem::size_of_val(slice)) }
+}
+
+impl<C> Pod<C> {
+	pub fn new(container: C) -> Self { Self(container) }
+
+	pub fn into_inner(self) -> C { self.0 }
+}
+
+impl<C> Deref for Pod<C> {
+	type Target = C;
+	fn deref(&self) -> &C { &self.0 }
+}
+
+impl<C: PodSlice> Serialize for Pod<C> {
+	fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+		let slice = self.0.pod_slice();
+		if cfg!(target_endian = "little") {
+			ser.serialize_bytes(bytes(slice))
+		} else {
+			ser.serialize_bytes(bytes(&slice.iter().map(|elem| elem.swap_le()).collect::<Vec<_>>()))
+		}
+	}
+}
+
+// the bound is on the struct so that `ReadBuf` can be specialized for it
+struct PodVisitor<C: PodBuf>(PhantomData<C>);
+
+impl<C: PodBuf> PodVisitor<C> {
+	// how many elements `len` bytes hold
+	fn elems<E: de::Error>(len: usize) -> Result<usize, E> {
+		let size = mem::size_of::<C::Elem>();
+		if len % size != 0 {
+			return Err(E::invalid_length(len, &"a whole number of elements"));
+		}
+		Ok(len / size)
+	}
+
+	fn zeroed<E: de::Error>(len: usize) -> Result<C, E> {
+		let elems = Self::elems(len)?;
+		C::zeroed(elems).ok_or_else(|| E::invalid_length(elems, &"as many elements as the array"))
+	}
+
+	fn swap(mut pod: C) -> Pod<C> {
+		if cfg!(target_endian = "big") {
+			for elem in pod.pod_slice_mut() { *elem = elem.swap_le(); }
+		}
+		Pod(pod)
+	}
+}
+
+impl<'de, C: PodBuf> Visitor<'de> for PodVisitor<C> {
+	type Value = Pod<C>;
+
+	fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		fmt.write_str("little-endian bytes")
+	}
+
+	fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Pod<C>, E> {
+		let mut pod = Self::zeroed(v.len())?;
+		bytes_mut(pod.pod_slice_mut()).copy_from_slice(v);
+		Ok(Self::swap(pod))
+	}
+
+	fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Pod<C>, A::Error> {
+		let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(1 << 16));
+		while let Some(byte) = seq.next_element()? { bytes.push(byte); }
+		self.visit_bytes(&bytes)
+	}
+}
+
+impl<'de, C: PodBuf> ReadBuf<'de> for PodVisitor<C> {
+	fn read_buf<R: ?Sized + Read>(self, de: &mut BytesDe<'de, R>) -> Result<Pod<C>, Error> {
+		let len = de.de_usize_alloc()?;
+		if de.discarding() {
+			Self::elems::<Error>(len)?;
+			de.discard_buf(len, false)?;
+			return C::zeroed(0).map_or_else(|| Self::zeroed(len), Ok).map(Pod);
+		}
+
+		let mut pod = Self::zeroed::<Error>(len)?;
+		de.rex(bytes_mut(pod.pod_slice_mut()))?;
+		Ok(Self::swap(pod))
+	}
+}
+
+impl<'de, C: PodBuf> Deserialize<'de> for Pod<C> {
+	fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+		de.deserialize_byte_buf(PodVisitor(PhantomData))
+	}
+}
@@ -0,0 +1,97 @@
+use {
+	super::{BytesSer, BytesDe, Error, ser},
+	crate::maps::Int,
+	serde::{Serialize, de::DeserializeOwned},
+	std::{any::{TypeId, type_name}, io::Read, marker::Unsize},
+};
+
+// lets a registry tell which concrete type is behind a trait object; make it a
+// supertrait of the registered trait
+pub trait DynType: 'static {
+	fn dyn_type_id(&self) -> TypeId;
+}
+
+impl<T: 'static> DynType for T {
+	fn dyn_type_id(&self) -> TypeId { TypeId::of::<T>() }
+}
+
+// encodes trait objects as the varint ID their concrete type was registered
+// with, followed by the concrete value
+pub struct Registry<T: ?Sized> {
+	entries: Int<u32, Entry<T>>,
+	ids: Int<TypeId, u32>,
+	names: Int<&'static str, u32>,
+}
+
+type SerFn<T> = fn(&T, &mut BytesSer) -> ser::Result;
+type DeFn<T> = fn(&mut BytesDe<'_, dyn Read + '_>) -> Result<Box<T>, Error>;
+
+struct Entry<T: ?Sized> {
+	name: &'static str,
+	ser: SerFn<T>,
+	de: DeFn<T>,
+}
+
+fn ser_as<T: ?Sized, C: Serialize>(value: &T, ser: &mut BytesSer) -> ser::Result {
+	// `Registry::serialize` checked that `value` is a `C`
+	ser.serialize(unsafe { &*(value as *const T as *const C) })
+}
+
+fn de_as<T: ?Sized, C: DeserializeOwned + Unsize<T>>(
+	de: &mut BytesDe<'_, dyn Read + '_>,
+) -> Result<Box<T>, Error> {
+	let value: Box<C> = Box::new(de.deserialize()?);
+	Ok(value)
+}
+
+impl<T: ?Sized> Default for Registry<T> {
+	fn default() -> Self {
+		Self { entries: Int::default(), ids: Int::default(), names: Int::default() }
+	}
+}
+
+impl<T: ?Sized + DynType> Registry<T> {
+	pub fn new() -> Self { Self::default() }
+
+	// `id` and `name` must be unique in the registry, and stay the same for as
+	// long as encoded values are around
+	pub fn register<C>(&mut self, id: u32, name: &'static str) -> &mut Self where
+		C: Serialize + DeserializeOwned + Unsize<T> + 'static,
+	{
+		assert!(!self.entries.contains_key(&id), "type ID {} registered twice", id);
+		assert!(!self.names.contains_key(name), "type name {} registered twice", name);
+		assert!(
+			!self.ids.contains_key(&TypeId::of::<C>()),
+			"{} registered twice", type_name::<C>(),
+		);
+
+		self.ids.insert(TypeId::of::<C>(), id);
+		self.names.insert(name, id);
+		self.entries.insert(id, Entry { name, ser: ser_as::<T, C>, de: de_as::<T, C> });
+		self
+	}
+
+	pub fn id(&self, name: &str) -> Option<u32> { self.names.get(name).copied() }
+
+	pub fn name(&self, id: u32) -> Option<&'static str> {
+		self.entries.get(&id).map(|entry| entry.name)
+	}
+
+	pub fn id_of(&self, value: &T) -> Option<u32> {
+		self.ids.get(&value.dyn_type_id()).copied()
+	}
+
+	pub fn serialize(&self, value: &T, ser: &mut BytesSer) -> ser::Result {
+		let id = self.id_of(value).ok_or_else(|| ser::Error::Custom(
+			format!("unregistered implementor of {}", type_name::<T>())
+		))?;
+		ser.serialize(&id)?;
+		(self.entries[&id].ser)(value, ser)
+	}
+
+	pub fn deserialize<R: Read>(&self, de: &mut BytesDe<'_, R>) -> Result<Box<T>, Error> {
+		let id = de.deserialize::<u32>()?;
+		let entry = self.entries.get(&id).ok_or(Error::UnknownTypeId(id))?;
+		de.with_dyn_read(|de| (entry.de)(de))
+	}
+}
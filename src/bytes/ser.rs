@@ -4,26 +4,29 @@ use {
 };
 
 #[derive(Debug)]
-#[allow(unreachable_code)]
-pub struct Infallible(!);
-
-impl From<Infallible> for ! {
-	fn from(Infallible(never): Infallible) -> ! { never }
+pub enum Error {
+	Custom(String),
+	LengthMismatch { expected: usize, actual: usize },
 }
 
-impl std::error::Error for Infallible {}
+impl std::error::Error for Error {}
 
-impl serde::ser::Error for Infallible {
-	fn custom<T: std::fmt::Display>(_: T) -> Self { unreachable!() }
+impl serde::ser::Error for Error {
+	fn custom<T: std::fmt::Display>(msg: T) -> Self { Self::Custom(format!("{}", msg)) }
 }
 
-impl std::fmt::Display for Infallible {
-	fn fmt(&self, _: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		self.0
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Custom(e) => write!(f, "{}", e),
+			Self::LengthMismatch { expected, actual } => write!(
+				f, "announced a length of {} but serialized {} elements", expected, actual,
+			),
+		}
 	}
 }
 
-pub type Result<T = ()> = std::result::Result<T, Infallible>;
+pub type Result<T = ()> = std::result::Result<T, Error>;
 
 type Range = std::ops::Range<usize>;
 
@@ -36,33 +39,53 @@ pub struct BytesSer<B = crate::cvec::CVec<u8>, R = crate::cvec::CVec<Range>> {
 
 pub struct BytesSerLen<'a, B, R> {
 	ser: &'a mut BytesSer<B, R>,
-	opt_insert_len: usize,
+	opt_len: Option<usize>,
+	range_i: usize,
 	len: usize,
 }
 
+// a sequence of `u8`s or `i8`s is encoded like `serialize_bytes`, so when the
+// elements come from a slice they can be copied in one go
+trait ByteSlice {
+	fn byte_slice(&self) -> Option<&[u8]>;
+}
+
+impl<I> ByteSlice for I {
+	default fn byte_slice(&self) -> Option<&[u8]> { None }
+}
+
+impl ByteSlice for std::slice::Iter<'_, u8> {
+	fn byte_slice(&self) -> Option<&[u8]> { Some(self.as_slice()) }
+}
+
+impl ByteSlice for std::slice::Iter<'_, i8> {
+	fn byte_slice(&self) -> Option<&[u8]> {
+		let slice = self.as_slice();
+		Some(unsafe { std::slice::from_raw_parts(slice.as_ptr() as *const u8, slice.len()) })
+	}
+}
+
 impl<'a, B, R> BytesSerLen<'a, B, R> where
 	B: VecExt<u8>,
 	R: VecExt<Range>,
 {
 	fn new(ser: &'a mut BytesSer<B, R>, opt_len: Option<usize>) -> Self {
-		let opt_insert_len = match opt_len {
+		let range_i = match opt_len {
 			Some(len) => { ser.ser_usize(len); usize::max_value() },
-			None => {
-				let range_i = ser.ranges.len();
-				ser.ranges.extend_one(usize::max_value() .. usize::max_value());
-				range_i
-			},
+			None => ser.begin_len(),
 		};
-		Self { ser, opt_insert_len, len: 0, }
+		Self { ser, opt_len, range_i, len: 0, }
 	}
 
-	fn end(self) {
-		if self.opt_insert_len != usize::max_value() {
-			let start = self.ser.buffer.len();
-			self.ser.ser_usize(self.len);
-			*unsafe { self.ser.ranges.get_unchecked_mut(self.opt_insert_len) } =
-				start .. self.ser.buffer.len();
-			self.ser.last_start = self.ser.buffer.len();
+	fn end(self) -> Result {
+		match self.opt_len {
+			Some(expected) if expected != self.len =>
+				Err(Error::LengthMismatch { expected, actual: self.len }),
+			Some(_) => Ok(()),
+			None => {
+				self.ser.end_len(self.range_i, self.len);
+				Ok(())
+			},
 		}
 	}
 }
@@ -93,6 +116,43 @@ impl<B, R> BytesSer<B, R> where
 
 	pub fn new() -> Self where B: Default, R: Default { Self::default() }
 
+	// moves `other`'s output after this one's, leaving `other` empty; only the
+	// buffer is copied, `other`'s spliced lengths stay where they are
+	pub fn append(&mut self, other: &mut Self) {
+		let offset = self.buffer.len();
+		self.push_range(offset);
+		for range in other.ranges.iter() {
+			self.ranges.extend_one(range.start + offset .. range.end + offset);
+		}
+		self.buffer.extend_copy_slice(&other.buffer);
+		self.last_start = other.last_start + offset;
+		other.clear();
+	}
+
+	// serializes `items` like a slice, encoding chunks of it on `threads`
+	// threads and appending their outputs in order
+	pub fn serialize_par<T: Serialize + Sync>(&mut self, items: &[T], threads: usize) -> Result where
+		B: Default + Send,
+		R: Default + Send,
+	{
+		let chunk_len = (items.len() + threads.max(1) - 1) / threads.max(1);
+		let mut chunks = std::thread::scope(|scope| {
+			items.chunks(chunk_len.max(1))
+				.map(|chunk| scope.spawn(move || {
+					let mut ser = Self::new();
+					chunk.iter().try_for_each(|item| ser.serialize(item)).map(|()| ser)
+				}))
+				.collect::<Vec<_>>()
+				.into_iter()
+				.map(|handle| handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+				.collect::<Result<Vec<_>>>()
+		})?;
+
+		self.ser_usize(items.len());
+		for chunk in &mut chunks { self.append(chunk); }
+		Ok(())
+	}
+
 	pub fn reuse_vecs(mut buffer: B, mut ranges: R) -> Self {
 		buffer.clear();
 		ranges.clear();
@@ -109,11 +169,35 @@ impl<B, R> BytesSer<B, R> where
 		(buffer, ranges, last_start)
 	}
 
+	fn push_range(&mut self, end: usize) {
+		if self.last_start != end {
+			self.ranges.extend_one(self.last_start .. end);
+		}
+	}
+
+	// leaves room for a length that's only known once the elements are written
+	pub(super) fn begin_len(&mut self) -> usize {
+		let start = self.buffer.len();
+		self.push_range(start);
+		let range_i = self.ranges.len();
+		self.ranges.extend_one(start .. start);
+		self.last_start = start;
+		range_i
+	}
+
+	pub(super) fn end_len(&mut self, range_i: usize, len: usize) {
+		let start = self.buffer.len();
+		self.push_range(start);
+		self.ser_usize(len);
+		*unsafe { self.ranges.get_unchecked_mut(range_i) } = start .. self.buffer.len();
+		self.last_start = self.buffer.len();
+	}
+
 	fn ecs(&mut self, s: &[u8]) { self.buffer.extend_copy_slice(s); }
 
 	fn e1(&mut self, b: u8) { self.buffer.extend_one(b); }
 
-	fn ser_usize(&mut self, mut v: usize) {
+	pub(super) fn ser_usize(&mut self, mut v: usize) {
 		loop {
 			let more = v >= 0x80;
 			self.e1((v as u8 & 0x7F) | ((more as u8) << 7));
@@ -276,7 +360,7 @@ impl<'a, B, R> serde::Serializer for &'a mut BytesSer<B, R> where
 	R: VecExt<Range>,
 {
 	type Ok = ();
-	type Error = Infallible;
+	type Error = Error;
 
 	type SerializeSeq = BytesSerLen<'a, B, R>;
 	type SerializeTuple = Self;
@@ -378,6 +462,28 @@ impl<'a, B, R> serde::Serializer for &'a mut BytesSer<B, R> where
 		Ok(BytesSerLen::new(self, opt_len))
 	}
 
+	fn collect_seq<I>(self, iter: I) -> Result where
+		I: IntoIterator,
+		I::Item: Serialize,
+	{
+		use serde::ser::SerializeSeq;
+
+		let iter = iter.into_iter();
+
+		if let Some(bytes) = iter.byte_slice() {
+			return self.serialize_bytes(bytes);
+		}
+
+		let opt_len = match iter.size_hint() {
+			(lo, Some(hi)) if lo == hi => Some(lo),
+			_ => None,
+		};
+
+		let mut seq = self.serialize_seq(opt_len)?;
+		for item in iter { seq.serialize_element(&item)?; }
+		seq.end()
+	}
+
 	fn serialize_tuple(self, _len: usize) -> Result<Self> { Ok(self) }
 
 	fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Self> {
@@ -422,14 +528,14 @@ impl<'a, B, R> serde::ser::SerializeSeq for BytesSerLen<'a, B, R> where
 	R: VecExt<Range>,
 {
 	type Ok = ();
-	type Error = Infallible;
+	type Error = Error;
 
 	fn serialize_element<U: ?Sized + Serialize>(&mut self, value: &U) -> Result {
 		self.len += 1;
 		value.serialize(&mut *self.ser)
 	}
 
-	fn end(self) -> Result { self.end(); Ok(()) }
+	fn end(self) -> Result { self.end() }
 }
 
 
@@ -438,7 +544,7 @@ impl<B, R> serde::ser::SerializeTuple for &'_ mut BytesSer<B, R> where
 	R: VecExt<Range>,
 {
 	type Ok = ();
-	type Error = Infallible;
+	type Error = Error;
 
 	fn serialize_element<U: ?Sized + Serialize>(&mut self, value: &U) -> Result {
 		value.serialize(&mut **self)
@@ -452,7 +558,7 @@ impl<B, R> serde::ser::SerializeTupleStruct for &'_ mut BytesSer<B, R> where
 	R: VecExt<Range>,
 {
 	type Ok = ();
-	type Error = Infallible;
+	type Error = Error;
 
 	fn serialize_field<U: ?Sized + Serialize>(&mut self, value: &U) -> Result {
 		value.serialize(&mut **self)
@@ -466,7 +572,7 @@ impl<B, R> serde::ser::SerializeTupleVariant for &'_ mut BytesSer<B, R> where
 	R: VecExt<Range>,
 {
 	type Ok = ();
-	type Error = Infallible;
+	type Error = Error;
 
 	fn serialize_field<U: ?Sized + Serialize>(&mut self, value: &U) -> Result {
 		value.serialize(&mut **self)
@@ -480,7 +586,7 @@ impl<'a, B, R> serde::ser::SerializeMap for BytesSerLen<'a, B, R> where
 	R: VecExt<Range>,
 {
 	type Ok = ();
-	type Error = Infallible;
+	type Error = Error;
 
 	fn serialize_key<U: ?Sized + Serialize>(&mut self, key: &U) -> Result {
 		self.len += 1;
@@ -491,7 +597,7 @@ impl<'a, B, R> serde::ser::SerializeMap for BytesSerLen<'a, B, R> where
 		value.serialize(&mut *self.ser)
 	}
 
-	fn end(self) -> Result { self.end(); Ok(()) }
+	fn end(self) -> Result { self.end() }
 }
 
 impl<B, R> serde::ser::SerializeStruct for &'_ mut BytesSer<B, R> where
@@ -499,7 +605,7 @@ impl<B, R> serde::ser::SerializeStruct for &'_ mut BytesSer<B, R> where
 	R: VecExt<Range>,
 {
 	type Ok = ();
-	type Error = Infallible;
+	type Error = Error;
 
 	fn serialize_field<U: ?Sized + Serialize>(
 		&mut self, _key: &'static str, value: &U,
@@ -515,7 +621,7 @@ impl<B, R> serde::ser::SerializeStructVariant for &'_ mut BytesSer<B, R> where
 	R: VecExt<Range>,
 {
 	type Ok = ();
-	type Error = Infallible;
+	type Error = Error;
 
 	fn serialize_field<U: ?Sized + Serialize>(
 		&mut self, _key: &'static str, value: &U,
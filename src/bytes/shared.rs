@@ -0,0 +1,195 @@
+use {
+	crate::{ByPtr, OnDrop, SArc, maps::Int},
+	serde::{
+		Serialize,
+		Serializer,
+		Deserialize,
+		Deserializer,
+		ser::Error as _,
+		de::{self, EnumAccess, VariantAccess},
+	},
+	std::{
+		any::{Any, TypeId},
+		cell::RefCell,
+		fmt,
+		marker::PhantomData,
+		ops::Deref,
+		rc::Rc,
+		sync::Arc,
+	},
+};
+
+// a pointer whose allocation is serialized once per `shared_scope`, as
+// `New(value)`, and then as `Ref(index)` into the allocations of its type
+// serialized so far; allocations are numbered once they're fully serialized,
+// so that both sides agree on the order
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Shared<P>(pub P);
+
+pub trait SharedPtr: Deref + Clone + 'static {
+	fn new(value: Self::Target) -> Self;
+}
+
+impl<T: 'static> SharedPtr for Rc<T> {
+	fn new(value: T) -> Self { Rc::new(value) }
+}
+
+impl<T: 'static> SharedPtr for Arc<T> {
+	fn new(value: T) -> Self { Arc::new(value) }
+}
+
+impl<T: 'static> SharedPtr for SArc<T> {
+	fn new(value: T) -> Self { SArc::new(value) }
+}
+
+// per pointer type: the allocations serialized so far, kept alive so that their
+// addresses can't be reused, and the allocations deserialized so far
+#[derive(Default)]
+struct Scope {
+	ser: Int<TypeId, Box<dyn Any>>,
+	de: Int<TypeId, Box<dyn Any>>,
+}
+
+thread_local! {
+	static SCOPE: RefCell<Option<Scope>> = RefCell::new(None);
+}
+
+pub fn shared_scope<R>(f: impl FnOnce() -> R) -> R {
+	let outer = SCOPE.with(|scope| scope.replace(Some(Scope::default())));
+	let _restore = OnDrop::new(outer, |outer| SCOPE.with(|scope| *scope.borrow_mut() = outer));
+	f()
+}
+
+fn with_scope<R>(f: impl FnOnce(&mut Scope) -> R) -> Option<R> {
+	SCOPE.with(|scope| scope.borrow_mut().as_mut().map(f))
+}
+
+const OUTSIDE_SCOPE: &str = "Shared used outside of a shared_scope";
+
+impl<P> Shared<P> {
+	pub fn new(ptr: P) -> Self { Self(ptr) }
+
+	pub fn into_inner(self) -> P { self.0 }
+}
+
+impl<P> Deref for Shared<P> {
+	type Target = P;
+	fn deref(&self) -> &P { &self.0 }
+}
+
+impl<P> Serialize for Shared<P> where
+	P: SharedPtr,
+	P::Target: Serialize,
+{
+	fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+		let key = ByPtr(self.0.clone());
+
+		let index = with_scope(|scope| scope.ser
+			.get(&TypeId::of::<P>())
+			.and_then(|table| table.downcast_ref::<Int<ByPtr<P>, usize>>()?.get(&key).copied())
+		).ok_or_else(|| S::Error::custom(OUTSIDE_SCOPE))?;
+
+		if let Some(index) = index {
+			return ser.serialize_newtype_variant("Shared", 1, "Ref", &index);
+		}
+
+		let ok = ser.serialize_newtype_variant("Shared", 0, "New", &*self.0)?;
+
+		with_scope(|scope| {
+			let table = scope.ser
+				.entry(TypeId::of::<P>())
+				.or_insert_with(|| Box::<Int<ByPtr<P>, usize>>::default())
+				.downcast_mut::<Int<ByPtr<P>, usize>>().unwrap();
+			let index = table.len();
+			table.insert(key, index);
+		});
+
+		Ok(ok)
+	}
+}
+
+enum Variant { New, Ref }
+
+impl<'de> Deserialize<'de> for Variant {
+	fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+		struct Visitor;
+
+		impl<'de> de::Visitor<'de> for Visitor {
+			type Value = Variant;
+
+			fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+				fmt.write_str("`New` or `Ref`")
+			}
+
+			fn visit_u64<E: de::Error>(self, v: u64) -> Result<Variant, E> {
+				match v {
+					0 => Ok(Variant::New),
+					1 => Ok(Variant::Ref),
+					_ => Err(E::invalid_value(de::Unexpected::Unsigned(v), &self)),
+				}
+			}
+
+			fn visit_str<E: de::Error>(self, v: &str) -> Result<Variant, E> {
+				match v {
+					"New" => Ok(Variant::New),
+					"Ref" => Ok(Variant::Ref),
+					_ => Err(E::unknown_variant(v, &["New", "Ref"])),
+				}
+			}
+		}
+
+		de.deserialize_identifier(Visitor)
+	}
+}
+
+impl<'de, P> Deserialize<'de> for Shared<P> where
+	P: SharedPtr,
+	P::Target: Deserialize<'de> + Sized,
+{
+	fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+		struct Visitor<P>(PhantomData<P>);
+
+		impl<'de, P> de::Visitor<'de> for Visitor<P> where
+			P: SharedPtr,
+			P::Target: Deserialize<'de> + Sized,
+		{
+			type Value = Shared<P>;
+
+			fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+				fmt.write_str("a shared pointer")
+			}
+
+			fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Shared<P>, A::Error> {
+				use de::Error as _;
+
+				with_scope(|_| ()).ok_or_else(|| A::Error::custom(OUTSIDE_SCOPE))?;
+
+				let ptr = match data.variant()? {
+					(Variant::New, variant) => {
+						let ptr = P::new(variant.newtype_variant()?);
+						with_scope(|scope| scope.de
+							.entry(TypeId::of::<P>())
+							.or_insert_with(|| Box::<Vec<P>>::default())
+							.downcast_mut::<Vec<P>>().unwrap()
+							.push(ptr.clone())
+						);
+						ptr
+					},
+					(Variant::Ref, variant) => {
+						let index = variant.newtype_variant::<usize>()?;
+						with_scope(|scope| scope.de
+							.get(&TypeId::of::<P>())
+							.and_then(|table| table.downcast_ref::<Vec<P>>()?.get(index).cloned())
+						).flatten().ok_or_else(|| A::Error::custom(format!(
+							"Shared refers to allocation {} before it was deserialized", index,
+						)))?
+					},
+				};
+
+				Ok(Shared(ptr))
+			}
+		}
+
+		de.deserialize_enum("Shared", &["New", "Ref"], Visitor(PhantomData))
+	}
+}
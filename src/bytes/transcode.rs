@@ -0,0 +1,455 @@
+use {
+	super::{BytesSer, BytesDe, Error, Schema, Format, Container, VariantFormat},
+	serde::{
+		Serialize,
+		Serializer,
+		Deserialize,
+		Deserializer,
+		ser::{self, SerializeSeq, SerializeMap, SerializeTuple, SerializeTupleStruct, SerializeStruct},
+		ser::{SerializeTupleVariant, SerializeStructVariant},
+		de::{self, Visitor, SeqAccess, MapAccess, EnumAccess, VariantAccess, DeserializeSeed, IgnoredAny},
+		de::Error as _,
+	},
+	std::{cell::RefCell, fmt, io::Read},
+};
+
+const MAX_DEPTH: usize = 128;
+
+// converts between the `bytes` encoding of a type and self-describing formats,
+// following the type's traced schema, without building the value in between
+pub struct Transcoder {
+	schema: Schema,
+}
+
+impl Transcoder {
+	pub fn new<'de, T: Deserialize<'de>>() -> Result<Self, Error> {
+		Ok(Self { schema: Schema::trace::<T>()? })
+	}
+
+	pub fn schema(&self) -> &Schema { &self.schema }
+
+	// reads one value from `de`, feeding it to `ser` as it goes
+	pub fn to_serializer<R: Read, S: Serializer>(
+		&self,
+		de: &mut BytesDe<'_, R>,
+		ser: S,
+	) -> Result<S::Ok, S::Error> {
+		let de = RefCell::new(de);
+		View { schema: &self.schema, format: &self.schema.root, de: &de, depth: MAX_DEPTH }.serialize(ser)
+	}
+
+	// reads one value from a self-describing `de` and writes it to `ser`
+	pub fn from_deserializer<'de, D: Deserializer<'de>>(
+		&self,
+		de: D,
+		ser: &mut BytesSer,
+	) -> Result<(), D::Error> {
+		Shape { schema: &self.schema, format: &self.schema.root, ser }.deserialize(de)
+	}
+
+	#[cfg(feature = "serde_json")]
+	pub fn to_json<R: Read, W: std::io::Write>(
+		&self,
+		de: &mut BytesDe<'_, R>,
+		out: W,
+		pretty: bool,
+	) -> Result<(), Error> {
+		let result = match pretty {
+			true => self.to_serializer(de, &mut serde_json::Serializer::pretty(out)),
+			false => self.to_serializer(de, &mut serde_json::Serializer::new(out)),
+		};
+		result.map_err(|e| Error::Custom(e.to_string()))
+	}
+
+	#[cfg(feature = "serde_json")]
+	pub fn from_json(&self, json: &str, ser: &mut BytesSer) -> Result<(), Error> {
+		let mut de = serde_json::Deserializer::from_str(json);
+		self.from_deserializer(&mut de, ser)
+			.and_then(|()| de.end())
+			.map_err(|e| Error::Custom(e.to_string()))
+	}
+}
+
+struct View<'a, 'b, 'de, R> {
+	schema: &'a Schema,
+	format: &'a Format,
+	de: &'a RefCell<&'b mut BytesDe<'de, R>>,
+	depth: usize,
+}
+
+impl<'a, 'b, 'de, R: Read> View<'a, 'b, 'de, R> {
+	fn at(&self, format: &'a Format) -> Self {
+		Self { format, depth: self.depth - 1, ..*self }
+	}
+}
+
+impl<'a, 'b, 'de, R> Clone for View<'a, 'b, 'de, R> {
+	fn clone(&self) -> Self { *self }
+}
+
+impl<'a, 'b, 'de, R> Copy for View<'a, 'b, 'de, R> {}
+
+impl<'a, 'b, 'de, R: Read> Serialize for View<'a, 'b, 'de, R> {
+	fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+		use ser::Error as _;
+
+		// the borrow has to end before `self.de` is needed again by nested views
+		macro_rules! read {
+			($t:ty) => {{
+				let value = self.de.borrow_mut().deserialize::<$t>();
+				value.map_err(S::Error::custom)?
+			}};
+		}
+
+		if self.depth == 0 { return Err(S::Error::custom(Error::DepthExceeded)); }
+
+		match self.format {
+			Format::Unknown => Err(S::Error::custom("can't transcode an untraced type")),
+			Format::Bool => ser.serialize_bool(read!(bool)),
+			Format::U8 => ser.serialize_u8(read!(u8)),
+			Format::U16 => ser.serialize_u16(read!(u16)),
+			Format::U32 => ser.serialize_u32(read!(u32)),
+			Format::U64 => ser.serialize_u64(read!(u64)),
+			Format::U128 => ser.serialize_u128(read!(u128)),
+			Format::I8 => ser.serialize_i8(read!(i8)),
+			Format::I16 => ser.serialize_i16(read!(i16)),
+			Format::I32 => ser.serialize_i32(read!(i32)),
+			Format::I64 => ser.serialize_i64(read!(i64)),
+			Format::I128 => ser.serialize_i128(read!(i128)),
+			Format::F32 => ser.serialize_f32(read!(f32)),
+			Format::F64 => ser.serialize_f64(read!(f64)),
+			Format::Char => ser.serialize_char(read!(char)),
+			Format::Str => ser.serialize_str(&read!(String)),
+			Format::Bytes => ser.serialize_bytes(&read!(Vec<u8>)),
+			Format::Unit => ser.serialize_unit(),
+			Format::Option(format) => match read!(bool) {
+				true => ser.serialize_some(&self.at(format)),
+				false => ser.serialize_none(),
+			},
+			Format::Seq(format) => {
+				let len = self.de.borrow_mut().de_usize_alloc().map_err(S::Error::custom)?;
+				let mut seq = ser.serialize_seq(Some(len))?;
+				for _ in 0 .. len { seq.serialize_element(&self.at(format))?; }
+				seq.end()
+			},
+			Format::Map(key, value) => {
+				let len = self.de.borrow_mut().de_usize_alloc().map_err(S::Error::custom)?;
+				let mut map = ser.serialize_map(Some(len))?;
+				for _ in 0 .. len { map.serialize_entry(&self.at(key), &self.at(value))?; }
+				map.end()
+			},
+			Format::Tuple(formats) => {
+				let mut tuple = ser.serialize_tuple(formats.len())?;
+				for format in formats { tuple.serialize_element(&self.at(format))?; }
+				tuple.end()
+			},
+			Format::Named(name) => match self.schema.containers.get(name) {
+				None => Err(S::Error::custom("can't transcode an untraced type")),
+				Some(Container::UnitStruct) => ser.serialize_unit_struct(name),
+				Some(Container::NewtypeStruct(format)) => ser.serialize_newtype_struct(name, &self.at(format)),
+				Some(Container::TupleStruct(formats)) => {
+					let mut tuple = ser.serialize_tuple_struct(name, formats.len())?;
+					for format in formats { tuple.serialize_field(&self.at(format))?; }
+					tuple.end()
+				},
+				Some(Container::Struct(fields)) => {
+					let mut r#struct = ser.serialize_struct(name, fields.len())?;
+					for (field, format) in fields { r#struct.serialize_field(field, &self.at(format))?; }
+					r#struct.end()
+				},
+				Some(Container::Enum(variants)) => {
+					let index = read!(u32);
+					let (variant, format) = variants.get(index as usize)
+						.ok_or_else(|| S::Error::custom(Error::InvalidEnumDiscriminant(index)))?;
+
+					match format {
+						VariantFormat::Unknown => Err(S::Error::custom("can't transcode an untraced variant")),
+						VariantFormat::Unit => ser.serialize_unit_variant(name, index, variant),
+						VariantFormat::Newtype(format) => {
+							ser.serialize_newtype_variant(name, index, variant, &self.at(format))
+						},
+						VariantFormat::Tuple(formats) => {
+							let mut tuple = ser.serialize_tuple_variant(name, index, variant, formats.len())?;
+							for format in formats { tuple.serialize_field(&self.at(format))?; }
+							tuple.end()
+						},
+						VariantFormat::Struct(fields) => {
+							let mut r#struct = ser.serialize_struct_variant(name, index, variant, fields.len())?;
+							for (field, format) in fields { r#struct.serialize_field(field, &self.at(format))?; }
+							r#struct.end()
+						},
+					}
+				},
+			},
+		}
+	}
+}
+
+struct Shape<'a> {
+	schema: &'a Schema,
+	format: &'a Format,
+	ser: &'a mut BytesSer,
+}
+
+fn put<'de, T, D>(de: D, ser: &mut BytesSer) -> Result<(), D::Error> where
+	T: Deserialize<'de> + Serialize,
+	D: Deserializer<'de>,
+{
+	let value = T::deserialize(de)?;
+	ser.serialize(&value).map_err(D::Error::custom)
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for Shape<'a> {
+	type Value = ();
+
+	fn deserialize<D: Deserializer<'de>>(self, de: D) -> Result<(), D::Error> {
+		let Shape { schema, format, ser } = self;
+
+		match format {
+			Format::Unknown => Err(D::Error::custom("can't transcode an untraced type")),
+			Format::Bool => put::<bool, _>(de, ser),
+			Format::U8 => put::<u8, _>(de, ser),
+			Format::U16 => put::<u16, _>(de, ser),
+			Format::U32 => put::<u32, _>(de, ser),
+			Format::U64 => put::<u64, _>(de, ser),
+			Format::U128 => put::<u128, _>(de, ser),
+			Format::I8 => put::<i8, _>(de, ser),
+			Format::I16 => put::<i16, _>(de, ser),
+			Format::I32 => put::<i32, _>(de, ser),
+			Format::I64 => put::<i64, _>(de, ser),
+			Format::I128 => put::<i128, _>(de, ser),
+			Format::F32 => put::<f32, _>(de, ser),
+			Format::F64 => put::<f64, _>(de, ser),
+			Format::Char => put::<char, _>(de, ser),
+			Format::Str => put::<String, _>(de, ser),
+			Format::Bytes => put::<Vec<u8>, _>(de, ser),
+			Format::Unit => put::<(), _>(de, ser),
+			Format::Option(format) => de.deserialize_option(OptionVisitor(Shape { schema, format, ser })),
+			Format::Seq(format) => de.deserialize_seq(SeqVisitor { schema, formats: Elements::Repeat(format), ser }),
+			Format::Map(key, value) => de.deserialize_map(MapVisitor { schema, key, value, ser }),
+			Format::Tuple(formats) => {
+				de.deserialize_tuple(formats.len(), SeqVisitor { schema, formats: Elements::fixed(formats), ser })
+			},
+			Format::Named(name) => match schema.containers.get(name) {
+				None => Err(D::Error::custom("can't transcode an untraced type")),
+				Some(Container::UnitStruct) => de.deserialize_unit_struct(name, UnitVisitor),
+				Some(Container::NewtypeStruct(format)) => Shape { schema, format, ser }.deserialize(de),
+				Some(Container::TupleStruct(formats)) => de.deserialize_tuple_struct(
+					name,
+					formats.len(),
+					SeqVisitor { schema, formats: Elements::fixed(formats), ser },
+				),
+				Some(Container::Struct(fields)) => {
+					de.deserialize_struct(name, &[], StructVisitor { schema, fields, ser })
+				},
+				Some(Container::Enum(variants)) => de.deserialize_enum(name, &[], EnumVisitor { schema, variants, ser }),
+			},
+		}
+	}
+}
+
+struct OptionVisitor<'a>(Shape<'a>);
+
+impl<'de, 'a> Visitor<'de> for OptionVisitor<'a> {
+	type Value = ();
+
+	fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result { fmt.write_str("an option") }
+
+	fn visit_none<E: de::Error>(self) -> Result<(), E> {
+		self.0.ser.serialize(&false).map_err(E::custom)
+	}
+
+	fn visit_unit<E: de::Error>(self) -> Result<(), E> { self.visit_none() }
+
+	fn visit_some<D: Deserializer<'de>>(self, de: D) -> Result<(), D::Error> {
+		self.0.ser.serialize(&true).map_err(D::Error::custom)?;
+		self.0.deserialize(de)
+	}
+}
+
+struct UnitVisitor;
+
+impl<'de> Visitor<'de> for UnitVisitor {
+	type Value = ();
+
+	fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result { fmt.write_str("a unit") }
+
+	fn visit_unit<E: de::Error>(self) -> Result<(), E> { Ok(()) }
+}
+
+enum Elements<'a> {
+	Repeat(&'a Format),
+	Fixed(Vec<&'a Format>),
+}
+
+impl<'a> Elements<'a> {
+	fn fixed(formats: &'a [Format]) -> Self { Elements::Fixed(formats.iter().collect()) }
+}
+
+struct SeqVisitor<'a> {
+	schema: &'a Schema,
+	formats: Elements<'a>,
+	ser: &'a mut BytesSer,
+}
+
+impl<'de, 'a> Visitor<'de> for SeqVisitor<'a> {
+	type Value = ();
+
+	fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result { fmt.write_str("a sequence") }
+
+	fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+		let SeqVisitor { schema, formats, ser } = self;
+
+		match formats {
+			Elements::Repeat(format) => {
+				let range = ser.begin_len();
+				let mut len = 0;
+				while let Some(()) = seq.next_element_seed(Shape { schema, format, ser: &mut *ser })? {
+					len += 1;
+				}
+				ser.end_len(range, len);
+			},
+			Elements::Fixed(formats) => {
+				for (i, &format) in formats.iter().enumerate() {
+					seq.next_element_seed(Shape { schema, format, ser: &mut *ser })?
+						.ok_or_else(|| A::Error::invalid_length(i, &self::Expected(formats.len())))?;
+				}
+				if seq.next_element::<IgnoredAny>()?.is_some() {
+					return Err(A::Error::invalid_length(formats.len() + 1, &self::Expected(formats.len())));
+				}
+			},
+		}
+		Ok(())
+	}
+}
+
+struct Expected(usize);
+
+impl de::Expected for Expected {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result { write!(fmt, "{} elements", self.0) }
+}
+
+struct MapVisitor<'a> {
+	schema: &'a Schema,
+	key: &'a Format,
+	value: &'a Format,
+	ser: &'a mut BytesSer,
+}
+
+impl<'de, 'a> Visitor<'de> for MapVisitor<'a> {
+	type Value = ();
+
+	fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result { fmt.write_str("a map") }
+
+	fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+		let MapVisitor { schema, key, value, ser } = self;
+		let range = ser.begin_len();
+		let mut len = 0;
+		while let Some(()) = map.next_key_seed(Shape { schema, format: key, ser: &mut *ser })? {
+			map.next_value_seed(Shape { schema, format: value, ser: &mut *ser })?;
+			len += 1;
+		}
+		ser.end_len(range, len);
+		Ok(())
+	}
+}
+
+struct StructVisitor<'a> {
+	schema: &'a Schema,
+	fields: &'a [(&'static str, Format)],
+	ser: &'a mut BytesSer,
+}
+
+impl<'de, 'a> Visitor<'de> for StructVisitor<'a> {
+	type Value = ();
+
+	fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result { fmt.write_str("a struct") }
+
+	fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<(), A::Error> {
+		let formats = Elements::Fixed(self.fields.iter().map(|(_, format)| format).collect());
+		SeqVisitor { schema: self.schema, formats, ser: self.ser }.visit_seq(seq)
+	}
+
+	// fields can come in any order, so each is encoded on its own first
+	fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+		let StructVisitor { schema, fields, ser } = self;
+		let mut encoded = fields.iter().map(|_| None).collect::<Vec<Option<BytesSer>>>();
+
+		while let Some(key) = map.next_key::<String>()? {
+			let i = match fields.iter().position(|(field, _)| *field == key) {
+				Some(i) => i,
+				None => { map.next_value::<IgnoredAny>()?; continue; },
+			};
+			if encoded[i].is_some() { return Err(A::Error::duplicate_field(fields[i].0)); }
+
+			let mut field = BytesSer::new();
+			map.next_value_seed(Shape { schema, format: &fields[i].1, ser: &mut field })?;
+			encoded[i] = Some(field);
+		}
+
+		for ((name, format), field) in fields.iter().zip(encoded) {
+			match (field, format) {
+				(Some(mut field), _) => ser.append(&mut field),
+				(None, Format::Option(_)) => ser.serialize(&false).map_err(A::Error::custom)?,
+				(None, _) => return Err(A::Error::missing_field(name)),
+			}
+		}
+		Ok(())
+	}
+}
+
+struct EnumVisitor<'a> {
+	schema: &'a Schema,
+	variants: &'a [(&'static str, VariantFormat)],
+	ser: &'a mut BytesSer,
+}
+
+impl<'de, 'a> Visitor<'de> for EnumVisitor<'a> {
+	type Value = ();
+
+	fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result { fmt.write_str("an enum") }
+
+	fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<(), A::Error> {
+		let EnumVisitor { schema, variants, ser } = self;
+		let (index, variant) = data.variant_seed(VariantSeed(variants))?;
+		ser.serialize(&(index as u32)).map_err(A::Error::custom)?;
+
+		match &variants[index].1 {
+			VariantFormat::Unknown => Err(A::Error::custom("can't transcode an untraced variant")),
+			VariantFormat::Unit => variant.unit_variant(),
+			VariantFormat::Newtype(format) => variant.newtype_variant_seed(Shape { schema, format, ser }),
+			VariantFormat::Tuple(formats) => {
+				variant.tuple_variant(formats.len(), SeqVisitor { schema, formats: Elements::fixed(formats), ser })
+			},
+			VariantFormat::Struct(fields) => variant.struct_variant(&[], StructVisitor { schema, fields, ser }),
+		}
+	}
+}
+
+struct VariantSeed<'a>(&'a [(&'static str, VariantFormat)]);
+
+impl<'de, 'a> DeserializeSeed<'de> for VariantSeed<'a> {
+	type Value = usize;
+
+	fn deserialize<D: Deserializer<'de>>(self, de: D) -> Result<usize, D::Error> {
+		de.deserialize_identifier(self)
+	}
+}
+
+impl<'de, 'a> Visitor<'de> for VariantSeed<'a> {
+	type Value = usize;
+
+	fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result { fmt.write_str("a variant") }
+
+	fn visit_u64<E: de::Error>(self, v: u64) -> Result<usize, E> {
+		match v < self.0.len() as u64 {
+			true => Ok(v as usize),
+			false => Err(E::invalid_value(de::Unexpected::Unsigned(v), &self)),
+		}
+	}
+
+	fn visit_str<E: de::Error>(self, v: &str) -> Result<usize, E> {
+		self.0.iter().position(|(name, _)| *name == v)
+			.ok_or_else(|| E::invalid_value(de::Unexpected::Str(v), &self))
+	}
+}
@@ -2,25 +2,116 @@ use {
 	std::{
 		ptr::{self, NonNull},
 		iter::{IntoIterator, Iterator, TrustedLen, ExactSizeIterator},
-		mem::{size_of, forget, replace},
+		mem::{size_of, replace},
+		marker::PhantomData,
 		slice,
 		ops,
-		alloc::{AllocError, Layout, Global as Alloc, Allocator},
+		alloc::{Layout, Global, Allocator},
+		io,
 	},
 	crate::extend_ext::ExtendExt,
 };
 
 #[repr(C)]
-pub struct CVec<T> {
+pub struct CVec<T, A: Allocator = Global, G: Growth = Tight> {
 	data: NonNull<T>,
-	len: usize,
+	// the length, plus whatever `G` packs in with it to know the capacity
+	word: usize,
+	alloc: A,
+	_growth: PhantomData<G>,
 }
 
-impl<T> CVec<T> {
-	pub fn new() -> Self {
-		Self { len: 0, data: Alloc.allocate(Self::layout(0)).unwrap().cast() }
+/// Decides the capacity of a `CVec` from its length.
+///
+/// # Safety
+///
+/// `CVec` allocates and writes according to these, so every capacity `resized`
+/// and `reserved` return must be a power of two or zero and at least the
+/// length, and for those lengths and capacities `len(word(len, cap)) == len`
+/// and `cap(word(len, cap)) == cap`.
+pub unsafe trait Growth: 'static {
+	fn word(len: usize, cap: usize) -> usize;
+	fn len(word: usize) -> usize;
+	fn cap(word: usize) -> usize;
+	// the capacity to move to when the length of a vector with capacity `cap`
+	// goes from `old_len` to `len`
+	fn resized(old_len: usize, len: usize, cap: usize) -> usize;
+	// the capacity `reserve` moves to
+	fn reserved(len: usize, cap: usize, additional: usize) -> usize;
+}
+
+// the capacity is always the smallest power of two the length fits in, so the
+// length word is just the length; pushing and popping across a power of two
+// reallocates every time
+pub enum Tight {}
+
+unsafe impl Growth for Tight {
+	fn word(len: usize, _: usize) -> usize { len }
+	fn len(word: usize) -> usize { word }
+	fn cap(word: usize) -> usize { len_cap(word) }
+	fn resized(_: usize, len: usize, _: usize) -> usize { len_cap(len) }
+	fn reserved(_: usize, cap: usize, _: usize) -> usize { cap }
+}
+
+// grows like `Tight`, but only shrinks once the length drops below a quarter
+// of the capacity, and only when the length goes down, so that growing into a
+// reservation keeps it; the top bits of the length word hold log2 of the
+// capacity
+pub enum Hysteresis {}
+
+impl Hysteresis {
+	const SHIFT: u32 = usize::BITS - 6;
+}
+
+unsafe impl Growth for Hysteresis {
+	fn word(len: usize, cap: usize) -> usize {
+		// the length would spill into the capacity bits
+		assert!(len < 1 << Self::SHIFT, "capacity overflow");
+		debug_assert!(cap >= len);
+		let log = if cap == 0 { 0 } else { cap.trailing_zeros() as usize + 1 };
+		len | log << Self::SHIFT
+	}
+
+	fn len(word: usize) -> usize { word & ((1 << Self::SHIFT) - 1) }
+
+	fn cap(word: usize) -> usize {
+		match word >> Self::SHIFT {
+			0 => 0,
+			log => 1 << (log - 1),
+		}
+	}
+
+	fn resized(old_len: usize, len: usize, cap: usize) -> usize {
+		if len > cap {
+			len_cap(len)
+		} else if len < old_len && len < cap / 4 {
+			len_cap(len) * 2
+		} else {
+			cap
+		}
 	}
 
+	fn reserved(len: usize, cap: usize, additional: usize) -> usize {
+		cap.max(len_cap(len + additional))
+	}
+}
+
+pub type HCVec<T, A = Global> = CVec<T, A, Hysteresis>;
+
+fn len_cap(len: usize) -> usize {
+	let cap = match len {
+		0 => 0,
+		_ => 1 << (size_of::<usize>() as u32 * 8 - (len - 1).leading_zeros()),
+	};
+
+	debug_assert!(cap >= len);
+
+	cap
+}
+
+impl<T> CVec<T> {
+	pub fn new() -> Self { Self::new_in(Global) }
+
 	pub fn from_trusted_len(iter: impl TrustedLen<Item = T>) -> Self {
 		let mut v = Self::new();
 		v.extend_trusted_len(iter);
@@ -34,118 +125,113 @@ impl<T> CVec<T> {
 	}
 
 	pub fn into_raw(self) -> (NonNull<T>, usize) {
-		let pair = (self.data, self.len);
-		forget(self);
-		pair
+		let (data, len, Global) = self.into_raw_with_allocator();
+		(data, len)
 	}
 
+	/// # Safety
+	///
+	/// As for [`CVec::from_raw_in`], with memory from the global allocator.
 	pub unsafe fn from_raw(data: NonNull<T>, len: usize) -> Self {
-		Self { data, len }
+		Self::from_raw_in(data, len, Global)
 	}
+}
 
-	fn len_cap(len: usize) -> usize {
-		let cap = match len {
-			0 => 0,
-			_ => 1 << (size_of::<usize>() as u32 * 8 - (len - 1).leading_zeros()),
-		};
+impl<T, A: Allocator> CVec<T, A> {
+	pub fn into_raw_with_allocator(self) -> (NonNull<T>, usize, A) {
+		let this = std::mem::ManuallyDrop::new(self);
+		(this.data, this.word, unsafe { ptr::read(&this.alloc) })
+	}
 
-		debug_assert!(cap >= len);
+	/// # Safety
+	///
+	/// `len` is a `Tight` length word, like `into_raw_with_allocator` returns:
+	/// the number of initialized elements at `data`. `data` must have been
+	/// allocated by `alloc` for the capacity that length implies, the smallest
+	/// power of two it fits in, and be dangling if that's zero.
+	pub unsafe fn from_raw_in(data: NonNull<T>, len: usize, alloc: A) -> Self {
+		Self { data, word: len, alloc, _growth: PhantomData }
+	}
+}
 
-		cap
+impl<T, A: Allocator, G: Growth> CVec<T, A, G> {
+	pub fn new_in(alloc: A) -> Self {
+		Self { data: NonNull::dangling(), word: G::word(0, 0), alloc, _growth: PhantomData }
 	}
 
-	pub fn cap(&self) -> usize { Self::len_cap(self.len) }
+	pub fn allocator(&self) -> &A { &self.alloc }
+
+	pub fn len(&self) -> usize { G::len(self.word) }
+
+	pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+	pub fn cap(&self) -> usize { G::cap(self.word) }
 
 	fn layout(cap: usize) -> Layout {
 		debug_assert!(cap.count_ones() <= 1);
 		Layout::array::<T>(cap).unwrap()
 	}
 
+	// the allocator is never asked for zero-sized layouts, those are dangling
 	unsafe fn dealloc(&self) {
-		Alloc.deallocate(self.data.cast(), Self::layout(self.cap()));
+		if self.cap() > 0 && size_of::<T>() > 0 {
+			self.alloc.deallocate(self.data.cast(), Self::layout(self.cap()));
+		}
 	}
 
-	unsafe fn resize_cap(
-		&mut self, old_cap: usize, new_cap: usize,
-		f: unsafe fn(&Alloc, NonNull<u8>, Layout, Layout) ->
-			Result<NonNull<[u8]>, AllocError>,
-	) {
+	unsafe fn resize_cap(&mut self, old_cap: usize, new_cap: usize) {
 		debug_assert!(new_cap.count_ones() <= 1 && old_cap.count_ones() <= 1);
-		self.data = f(
-			&mut Alloc,
-			self.data.cast(),
-			Self::layout(old_cap),
-			Self::layout(new_cap),
-		).unwrap().cast();
+		self.data = match (old_cap, new_cap) {
+			_ if old_cap == new_cap || size_of::<T>() == 0 => return,
+			(0, _) => self.alloc.allocate(Self::layout(new_cap)).unwrap().cast(),
+			(_, 0) => {
+				self.alloc.deallocate(self.data.cast(), Self::layout(old_cap));
+				NonNull::dangling()
+			}
+			_ => {
+				let f = if new_cap > old_cap { A::grow } else { A::shrink };
+				f(
+					&self.alloc,
+					self.data.cast(),
+					Self::layout(old_cap),
+					Self::layout(new_cap),
+				).unwrap().cast()
+			}
+		};
 	}
 
-	unsafe fn grow_len(&mut self, new_len: usize) {
-		debug_assert!(new_len >= self.len);
-
+	// when shrinking, the elements past `new_len` must have been moved out or
+	// dropped already; when growing, they must be written right after
+	unsafe fn set_len(&mut self, new_len: usize) {
 		let old_cap = self.cap();
-		let new_cap = Self::len_cap(new_len);
-
-		if new_cap != old_cap {
-			self.resize_cap(old_cap, new_cap, Alloc::grow);
-		}
-
-		self.len = new_len;
+		let new_cap = G::resized(self.len(), new_len, old_cap);
+		self.resize_cap(old_cap, new_cap);
+		self.word = G::word(new_len, new_cap);
 	}
 
 	#[inline]
 	pub fn push(&mut self, elem: T) {
 		unsafe {
-			let old_len = self.len;
-
-			self.len += 1;
-
-			if old_len.count_ones() <= 1 {
-				let new_cap = self.cap();
-				debug_assert_eq!(new_cap >> 1, Self::len_cap(old_len));
-				self.resize_cap(new_cap >> 1, new_cap, Alloc::grow);
-			}
-
+			let old_len = self.len();
+			self.set_len(old_len + 1);
 			ptr::write(self.data.as_ptr().add(old_len), elem);
 		}
 	}
 
 	pub fn pop(&mut self) -> Option<T> {
-		if self.len > 0 {
-			unsafe {
-				let new_len = self.len - 1;
-
-				let elem = ptr::read(self.data.as_ptr().add(new_len));
-
-				if new_len.count_ones() <= 1 {
-					let old_cap = self.cap();
-					debug_assert_eq!(old_cap >> 1, Self::len_cap(new_len));
-					self.resize_cap(old_cap, old_cap >> 1, Alloc::shrink);
-				}
-
-				self.len = new_len;
-
-				Some(elem)
-			}
-		} else { None }
-	}
-
-	pub fn resize_with(&mut self, to: usize, mut with: impl FnMut() -> T) {
+		let new_len = self.len().checked_sub(1)?;
 		unsafe {
-			let range = self.len() .. to;
-
-			use std::cmp::Ordering::*;
-
-			if let Some(resize) = match to.cmp(&self.len()) {
-				Less => Some(Alloc::shrink as _),
-				Greater => Some(Alloc::grow as _),
-				Equal => None,
-			} {
-				self.resize_cap(Self::len_cap(self.len()), Self::len_cap(to), resize);
-			}
+			let elem = ptr::read(self.data.as_ptr().add(new_len));
+			self.set_len(new_len);
+			Some(elem)
+		}
+	}
 
-			for i in range {
-				self.data.as_ptr().add(i).write(with());
-			}
+	pub fn resize_with(&mut self, to: usize, with: impl FnMut() -> T) {
+		if to <= self.len() {
+			self.truncate(to);
+		} else {
+			self.extend_trusted_len(std::iter::repeat_with(with).take(to - self.len()));
 		}
 	}
 
@@ -157,21 +243,31 @@ impl<T> CVec<T> {
 
 	pub fn as_ptr(&self) -> *mut T { self.data.as_ptr() }
 
-	pub fn reserve(&self, _: usize) {} // intentionally a no-op
+	// a no-op with the `Tight` growth policy
+	pub fn reserve(&mut self, additional: usize) {
+		let (len, cap) = (self.len(), self.cap());
+		let new_cap = G::reserved(len, cap, additional);
+		unsafe { self.resize_cap(cap, new_cap); }
+		self.word = G::word(len, new_cap);
+	}
 
-	// for now just RangeFull (`..`)
-	pub fn drain(&mut self, _: std::ops::RangeFull) -> IntoIter<T> {
-		std::mem::replace(self, Self::new()).into_iter()
+	pub fn drain(&mut self, range: impl ops::RangeBounds<usize>) -> Drain<'_, T, A, G> {
+		let (start, end) = bounds(range, self.len());
+		// until the drain is dropped the vector is empty, so leaking it only
+		// leaks the buffer
+		let data = replace(&mut self.data, NonNull::dangling());
+		let word = replace(&mut self.word, G::word(0, 0));
+		Drain { vec: self, data, word, start, end, front: start, back: end }
 	}
 
 	pub fn clear(&mut self) {
-		*self = Self::new();
+		self.truncate(0);
 	}
 
 	unsafe fn extend_copy_unsafe(&mut self, data: &[T]) {
-		let pos = self.len;
+		let pos = self.len();
 
-		self.grow_len(self.len + data.len());
+		self.set_len(self.len() + data.len());
 
 		ptr::copy_nonoverlapping(
 			data.as_ptr(),
@@ -181,8 +277,11 @@ impl<T> CVec<T> {
 	}
 
 	pub fn clear_forget(&mut self) {
-		unsafe { self.dealloc(); }
-		forget(replace(self, Self::new()));
+		unsafe {
+			self.dealloc();
+			self.data = NonNull::dangling();
+			self.word = G::word(0, 0);
+		}
 	}
 
 	pub fn swap_remove(&mut self, index: usize) -> T {
@@ -192,62 +291,291 @@ impl<T> CVec<T> {
 		}
 		value
 	}
-}
 
-impl<T> Extend<T> for CVec<T> {
-	fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+	pub fn truncate(&mut self, len: usize) {
+		if len < self.len() {
+			self.drain(len ..);
+		}
+	}
+
+	pub fn insert(&mut self, index: usize, elem: T) {
+		let len = self.len();
+		assert!(index <= len, "insertion index {} out of bounds for length {}", index, len);
+
 		unsafe {
-			let iter = iter.into_iter();
+			self.set_len(len + 1);
+			let p = self.as_ptr().add(index);
+			ptr::copy(p, p.add(1), len - index);
+			ptr::write(p, elem);
+		}
+	}
 
-			let mut alloc_cap = Self::len_cap(self.len + iter.size_hint().0);
+	pub fn remove(&mut self, index: usize) -> T {
+		let len = self.len();
+		assert!(index < len, "removal index {} out of bounds for length {}", index, len);
 
-			self.resize_cap(self.cap(), alloc_cap, Alloc::grow);
+		unsafe {
+			let p = self.as_ptr().add(index);
+			let elem = ptr::read(p);
+			ptr::copy(p.add(1), p, len - index - 1);
+			self.set_len(len - 1);
+			elem
+		}
+	}
 
-			for (item, pos) in iter.zip(self.len ..) {
-				if pos == alloc_cap {
-					let new_alloc_cap = (alloc_cap * 2).max(1);
-					self.resize_cap(alloc_cap, new_alloc_cap, Alloc::grow);
-					alloc_cap = new_alloc_cap;
-				}
+	pub fn split_off(&mut self, at: usize) -> Self where A: Clone {
+		let len = self.len();
+		assert!(at <= len, "split index {} out of bounds for length {}", at, len);
+
+		let mut other = Self::new_in(self.alloc.clone());
+		unsafe {
+			other.set_len(len - at);
+			ptr::copy_nonoverlapping(self.as_ptr().add(at), other.as_ptr(), len - at);
+			self.set_len(at);
+		}
+		other
+	}
+
+	// unlike `Vec::splice` the replaced elements are removed eagerly
+	pub fn splice<I: IntoIterator<Item = T>>(
+		&mut self, range: impl ops::RangeBounds<usize>, replace_with: I,
+	) -> IntoIter<T, A, G> where A: Clone {
+		let (start, end) = bounds(range, self.len());
+		let mut tail = self.split_off(end);
+		let removed = self.split_off(start);
+		self.extend(replace_with);
+		self.extend_append_self(&mut tail);
+		removed.into_iter()
+	}
+
+	pub fn retain(&mut self, mut retain: impl FnMut(&T) -> bool) {
+		self.retain_mut(|elem| retain(elem));
+	}
+
+	pub fn retain_mut(&mut self, mut retain: impl FnMut(&mut T) -> bool) {
+		let mut compact = Compact { read: 0, write: 0, vec: self };
 
-				self.data.as_ptr().add(pos).write(item);
-				self.len += 1;
+		unsafe {
+			while compact.read < compact.vec.len() {
+				let p = compact.vec.as_ptr().add(compact.read);
+				let keep = retain(&mut *p);
+				compact.read += 1;
+				if keep {
+					compact.keep(p);
+				} else {
+					ptr::drop_in_place(p);
+				}
 			}
+		}
+	}
+
+	pub fn dedup_by(&mut self, mut same: impl FnMut(&mut T, &mut T) -> bool) {
+		if self.len() < 2 { return; }
 
-			let should_cap = Self::len_cap(self.len);
-			if should_cap != alloc_cap {
-				debug_assert!(should_cap < alloc_cap);
-				self.resize_cap(alloc_cap, should_cap, Alloc::shrink);
+		let mut compact = Compact { read: 1, write: 1, vec: self };
+
+		unsafe {
+			while compact.read < compact.vec.len() {
+				let base = compact.vec.as_ptr();
+				let p = base.add(compact.read);
+				let same = same(&mut *p, &mut *base.add(compact.write - 1));
+				compact.read += 1;
+				if same {
+					ptr::drop_in_place(p);
+				} else {
+					compact.keep(p);
+				}
 			}
 		}
 	}
 
-	fn extend_one(&mut self, val: T) { self.push(val); }
+	pub fn dedup_by_key<K: PartialEq>(&mut self, mut key: impl FnMut(&mut T) -> K) {
+		self.dedup_by(|a, b| key(a) == key(b));
+	}
+
+	pub fn dedup(&mut self) where T: PartialEq {
+		self.dedup_by(|a, b| a == b);
+	}
 }
 
-impl<T> ExtendExt<T> for CVec<T> {
-	fn extend_trusted_len(&mut self, elems: impl TrustedLen<Item = T>) {
+fn bounds(range: impl ops::RangeBounds<usize>, len: usize) -> (usize, usize) {
+	use ops::Bound::*;
+
+	let start = match range.start_bound() {
+		Included(&i) => i,
+		Excluded(&i) => i.checked_add(1).expect("range start overflows"),
+		Unbounded => 0,
+	};
+	let end = match range.end_bound() {
+		Included(&i) => i.checked_add(1).expect("range end overflows"),
+		Excluded(&i) => i,
+		Unbounded => len,
+	};
+
+	assert!(start <= end, "range starts at {} but ends at {}", start, end);
+	assert!(end <= len, "range end {} out of bounds for length {}", end, len);
+
+	(start, end)
+}
+
+// elements before `write` are kept, the ones from `read` on haven't been looked
+// at yet; on drop (even when unwinding) the latter are moved down to close the gap
+struct Compact<'a, T, A: Allocator, G: Growth> {
+	vec: &'a mut CVec<T, A, G>,
+	read: usize,
+	write: usize,
+}
+
+impl<T, A: Allocator, G: Growth> Compact<'_, T, A, G> {
+	unsafe fn keep(&mut self, elem: *mut T) {
+		let to = self.vec.as_ptr().add(self.write);
+		if to != elem {
+			ptr::copy_nonoverlapping(elem, to, 1);
+		}
+		self.write += 1;
+	}
+}
+
+impl<T, A: Allocator, G: Growth> Drop for Compact<'_, T, A, G> {
+	fn drop(&mut self) {
+		unsafe {
+			let len = self.vec.len();
+			let p = self.vec.as_ptr();
+			ptr::copy(p.add(self.read), p.add(self.write), len - self.read);
+			self.vec.set_len(len - (self.read - self.write));
+		}
+	}
+}
+
+pub struct Drain<'a, T, A: Allocator = Global, G: Growth = Tight> {
+	vec: &'a mut CVec<T, A, G>,
+	data: NonNull<T>,
+	word: usize,
+	start: usize,
+	end: usize,
+	front: usize,
+	back: usize,
+}
+
+impl<T, A: Allocator, G: Growth> Drain<'_, T, A, G> {
+	pub fn as_slice(&self) -> &[T] {
+		unsafe {
+			slice::from_raw_parts(self.data.as_ptr().add(self.front), self.back - self.front)
+		}
+	}
+}
+
+impl<T, A: Allocator, G: Growth> Iterator for Drain<'_, T, A, G> {
+	type Item = T;
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		let len = self.len();
+		(len, Some(len))
+	}
+
+	fn next(&mut self) -> Option<T> {
+		if self.front == self.back { return None; }
+		self.front += 1;
+		Some(unsafe { ptr::read(self.data.as_ptr().add(self.front - 1)) })
+	}
+}
+
+impl<T, A: Allocator, G: Growth> DoubleEndedIterator for Drain<'_, T, A, G> {
+	fn next_back(&mut self) -> Option<T> {
+		if self.front == self.back { return None; }
+		self.back -= 1;
+		Some(unsafe { ptr::read(self.data.as_ptr().add(self.back)) })
+	}
+}
+
+impl<T, A: Allocator, G: Growth> ExactSizeIterator for Drain<'_, T, A, G> {
+	fn len(&self) -> usize { self.back - self.front }
+}
+
+unsafe impl<T, A: Allocator, G: Growth> TrustedLen for Drain<'_, T, A, G> {}
+
+impl<T, A: Allocator, G: Growth> Drop for Drain<'_, T, A, G> {
+	fn drop(&mut self) {
+		// the tail is moved back even if dropping an element panics, so that
+		// only the drained range can leak
+		let drain = crate::on_drop::OnDrop::new(self, |drain| unsafe {
+			let p = drain.data.as_ptr();
+			let len = G::len(drain.word);
+			ptr::copy(p.add(drain.end), p.add(drain.start), len - drain.end);
+
+			drain.vec.data = drain.data;
+			drain.vec.word = drain.word;
+			drain.vec.set_len(len - (drain.end - drain.start));
+		});
 		unsafe {
-			let pos = self.len;
+			let p = drain.data.as_ptr();
+			ptr::drop_in_place(ptr::slice_from_raw_parts_mut(p.add(drain.front), drain.back - drain.front));
+		}
+	}
+}
 
-			self.grow_len(self.len + elems.size_hint().1.unwrap());
+// lets the capacity run ahead of what the growth policy wants while elements
+// are written, and settles it on drop, as if they had been pushed one by one,
+// so that a panicking iterator leaves a valid vector
+struct Filling<'a, T, A: Allocator, G: Growth> {
+	vec: &'a mut CVec<T, A, G>,
+	len: usize,
+	cap: usize,
+	old_len: usize,
+	old_cap: usize,
+}
+
+impl<'a, T, A: Allocator, G: Growth> Filling<'a, T, A, G> {
+	fn new(vec: &'a mut CVec<T, A, G>, additional: usize) -> Self {
+		let (len, cap) = (vec.len(), vec.cap());
+		let new_cap = cap.max(len_cap(len + additional));
+		unsafe { vec.resize_cap(cap, new_cap); }
+		Self { vec, len, cap: new_cap, old_len: len, old_cap: cap }
+	}
 
-			for (elem, i) in elems.zip(pos ..) {
-				debug_assert!(i < self.len);
-				ptr::write(self.data.as_ptr().add(i), elem);
+	fn push(&mut self, elem: T) {
+		unsafe {
+			if self.len == self.cap {
+				let new_cap = (self.cap * 2).max(1);
+				self.vec.resize_cap(self.cap, new_cap);
+				self.cap = new_cap;
 			}
+
+			self.vec.as_ptr().add(self.len).write(elem);
+			self.len += 1;
 		}
 	}
+}
+
+impl<T, A: Allocator, G: Growth> Drop for Filling<'_, T, A, G> {
+	fn drop(&mut self) {
+		let cap = G::resized(self.old_len, self.len, self.old_cap);
+		unsafe { self.vec.resize_cap(self.cap, cap); }
+		self.vec.word = G::word(self.len, cap);
+	}
+}
+
+impl<T, A: Allocator, G: Growth> Extend<T> for CVec<T, A, G> {
+	fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+		let iter = iter.into_iter();
+		let mut filling = Filling::new(self, iter.size_hint().0);
+		iter.for_each(|item| filling.push(item));
+	}
+
+	fn extend_one(&mut self, val: T) { self.push(val); }
+}
+
+impl<T, A: Allocator, G: Growth> ExtendExt<T> for CVec<T, A, G> {
+	fn extend_trusted_len(&mut self, elems: impl TrustedLen<Item = T>) {
+		let mut filling = Filling::new(self, elems.size_hint().1.unwrap());
+		elems.for_each(|elem| filling.push(elem));
+	}
 
 	fn extend_copy_slice(&mut self, data: &[T]) where T: Copy {
 		unsafe { self.extend_copy_unsafe(data); }
 	}
 
 	fn extend_append_self(&mut self, rhs: &mut Self) {
-		self.extend_append_cvec(rhs);
-	}
-
-	fn extend_append_cvec(&mut self, rhs: &mut CVec<T>) {
 		if self.is_empty() {
 			std::mem::swap(self, rhs);
 		} else {
@@ -258,7 +586,30 @@ impl<T> ExtendExt<T> for CVec<T> {
 		}
 	}
 
+	fn extend_append_cvec(&mut self, rhs: &mut CVec<T>) {
+		Append::append_cvec(self, rhs);
+	}
+
 	fn extend_append_vec(&mut self, rhs: &mut Vec<T>) {
+		Append::append_vec(self, rhs);
+	}
+}
+
+// buffers can only be taken over when both sides use the global allocator
+trait Append<T> {
+	fn append_cvec(&mut self, rhs: &mut CVec<T>);
+	fn append_vec(&mut self, rhs: &mut Vec<T>);
+}
+
+impl<T, A: Allocator, G: Growth> Append<T> for CVec<T, A, G> {
+	default fn append_cvec(&mut self, rhs: &mut CVec<T>) {
+		unsafe {
+			self.extend_copy_unsafe(&**rhs);
+			rhs.clear_forget();
+		}
+	}
+
+	default fn append_vec(&mut self, rhs: &mut Vec<T>) {
 		unsafe {
 			self.extend_copy_unsafe(&**rhs);
 			rhs.set_len(0);
@@ -266,19 +617,74 @@ impl<T> ExtendExt<T> for CVec<T> {
 	}
 }
 
-impl<T> IntoIterator for CVec<T> {
-	type IntoIter = IntoIter<T>;
+impl<T> Append<T> for CVec<T> {
+	fn append_cvec(&mut self, rhs: &mut CVec<T>) {
+		self.extend_append_self(rhs);
+	}
+
+	fn append_vec(&mut self, rhs: &mut Vec<T>) {
+		if self.is_empty() {
+			*self = std::mem::take(rhs).into();
+		} else {
+			unsafe {
+				self.extend_copy_unsafe(&**rhs);
+				rhs.set_len(0);
+			}
+		}
+	}
+}
+
+// reuses the allocation if its capacity is already right for the length, or
+// else reallocates it once
+impl<T> From<Vec<T>> for CVec<T> {
+	fn from(vec: Vec<T>) -> Self {
+		let mut vec = std::mem::ManuallyDrop::new(vec);
+		let (data, len, cap) = (vec.as_mut_ptr(), vec.len(), vec.capacity());
+
+		let old = Layout::array::<T>(cap).unwrap();
+		let new = Self::layout(len_cap(len));
+
+		unsafe {
+			let data = NonNull::new_unchecked(data);
+			let data = if old.size() == new.size() {
+				data
+			} else if new.size() == 0 {
+				Global.deallocate(data.cast(), old);
+				NonNull::dangling()
+			} else if old.size() == 0 {
+				Global.allocate(new).unwrap().cast()
+			} else if old.size() < new.size() {
+				Global.grow(data.cast(), old, new).unwrap().cast()
+			} else {
+				Global.shrink(data.cast(), old, new).unwrap().cast()
+			};
+
+			Self::from_raw(data, len)
+		}
+	}
+}
+
+impl<T> From<CVec<T>> for Vec<T> {
+	fn from(vec: CVec<T>) -> Self {
+		let cap = vec.cap();
+		let (data, len) = vec.into_raw();
+		unsafe { Vec::from_raw_parts(data.as_ptr(), len, cap) }
+	}
+}
+
+impl<T, A: Allocator, G: Growth> IntoIterator for CVec<T, A, G> {
+	type IntoIter = IntoIter<T, A, G>;
 	type Item = T;
-	fn into_iter(self) -> IntoIter<T> { IntoIter { vec: self, pos: 0 } }
+	fn into_iter(self) -> IntoIter<T, A, G> { IntoIter { vec: self, pos: 0 } }
 }
 
-impl<'a, T> IntoIterator for &'a CVec<T> {
+impl<'a, T, A: Allocator, G: Growth> IntoIterator for &'a CVec<T, A, G> {
 	type IntoIter = std::slice::Iter<'a, T>;
 	type Item = &'a T;
 	fn into_iter(self) -> Self::IntoIter { (**self).iter() }
 }
 
-impl<'a, T> IntoIterator for &'a mut CVec<T> {
+impl<'a, T, A: Allocator, G: Growth> IntoIterator for &'a mut CVec<T, A, G> {
 	type IntoIter = std::slice::IterMut<'a, T>;
 	type Item = &'a mut T;
 	fn into_iter(self) -> Self::IntoIter { (**self).iter_mut() }
@@ -292,16 +698,25 @@ impl<T> std::iter::FromIterator<T> for CVec<T> {
 	}
 }
 
-pub struct IntoIter<T> { vec: CVec<T>, pos: usize }
+pub struct IntoIter<T, A: Allocator = Global, G: Growth = Tight> { vec: CVec<T, A, G>, pos: usize }
 
-impl<T> Drop for IntoIter<T> {
+impl<T, A: Allocator, G: Growth> IntoIter<T, A, G> {
+	pub fn allocator(&self) -> &A { self.vec.allocator() }
+}
+
+impl<T, A: Allocator, G: Growth> Drop for IntoIter<T, A, G> {
 	fn drop(&mut self) {
-		while let Some(_) = self.next() {}
-		self.vec.clear_forget();
+		let remaining = unsafe {
+			ptr::slice_from_raw_parts_mut(self.vec.as_ptr().add(self.pos), self.len())
+		};
+		// the buffer is freed even if dropping an element panics
+		let vec = crate::on_drop::OnDrop::new(&mut self.vec, |vec| vec.clear_forget());
+		unsafe { ptr::drop_in_place(remaining); }
+		drop(vec);
 	}
 }
 
-impl<T> Iterator for IntoIter<T> {
+impl<T, A: Allocator, G: Growth> Iterator for IntoIter<T, A, G> {
 	type Item = T;
 
 	fn size_hint(&self) -> (usize, Option<usize>) {
@@ -320,21 +735,21 @@ impl<T> Iterator for IntoIter<T> {
 	}
 }
 
-impl<T> ExactSizeIterator for IntoIter<T> {
-	fn len(&self) -> usize { self.vec.len - self.pos }
-	fn is_empty(&self) -> bool { self.vec.len == self.pos }
+impl<T, A: Allocator, G: Growth> ExactSizeIterator for IntoIter<T, A, G> {
+	fn len(&self) -> usize { self.vec.len() - self.pos }
+	fn is_empty(&self) -> bool { self.vec.len() == self.pos }
 }
 
-unsafe impl<T> TrustedLen for IntoIter<T> {}
+unsafe impl<T, A: Allocator, G: Growth> TrustedLen for IntoIter<T, A, G> {}
 
-impl<T> Default for CVec<T> {
-	fn default() -> Self { Self::new() }
+impl<T, A: Allocator + Default, G: Growth> Default for CVec<T, A, G> {
+	fn default() -> Self { Self::new_in(A::default()) }
 }
 
-impl<T> Drop for CVec<T> {
+impl<T, A: Allocator, G: Growth> Drop for CVec<T, A, G> {
 	fn drop(&mut self) {
 		unsafe {
-			for i in 0..self.len {
+			for i in 0..self.len() {
 				ptr::read(self.as_ptr().add(i));
 			}
 
@@ -343,31 +758,31 @@ impl<T> Drop for CVec<T> {
 	}
 }
 
-unsafe impl<T: Send> Send for CVec<T> {}
-unsafe impl<T: Sync> Sync for CVec<T> {}
+unsafe impl<T: Send, A: Allocator + Send, G: Growth> Send for CVec<T, A, G> {}
+unsafe impl<T: Sync, A: Allocator + Sync, G: Growth> Sync for CVec<T, A, G> {}
 
-impl<T: Clone> Clone for CVec<T> {
+impl<T: Clone, A: Allocator + Clone, G: Growth> Clone for CVec<T, A, G> {
 	fn clone(&self) -> Self {
-		let mut v = Self::new();
+		let mut v = Self::new_in(self.alloc.clone());
 		v.extend_trusted_len(self.iter().cloned());
 		v
 	}
 }
 
-impl<T> ops::Deref for CVec<T> {
+impl<T, A: Allocator, G: Growth> ops::Deref for CVec<T, A, G> {
 	type Target = [T];
 	fn deref(&self) -> &[T] {
-		unsafe { slice::from_raw_parts(self.as_ptr(), self.len) }
+		unsafe { slice::from_raw_parts(self.as_ptr(), self.len()) }
 	}
 }
 
-impl<T> ops::DerefMut for CVec<T> {
+impl<T, A: Allocator, G: Growth> ops::DerefMut for CVec<T, A, G> {
 	fn deref_mut(&mut self) -> &mut [T] {
-		unsafe { slice::from_raw_parts_mut(self.as_ptr(), self.len) }
+		unsafe { slice::from_raw_parts_mut(self.as_ptr(), self.len()) }
 	}
 }
 
-impl<T: std::fmt::Debug> std::fmt::Debug for CVec<T> {
+impl<T: std::fmt::Debug, A: Allocator, G: Growth> std::fmt::Debug for CVec<T, A, G> {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) ->
 		Result<(), std::fmt::Error>
 	{
@@ -375,20 +790,20 @@ impl<T: std::fmt::Debug> std::fmt::Debug for CVec<T> {
 	}
 }
 
-impl<T: PartialEq> PartialEq for CVec<T> {
+impl<T: PartialEq, A: Allocator, G: Growth> PartialEq for CVec<T, A, G> {
 	fn eq(&self, rhs: &Self) -> bool { **self == **rhs }
 	fn ne(&self, rhs: &Self) -> bool { **self != **rhs }
 }
 
-impl<T: Eq> Eq for CVec<T> {}
+impl<T: Eq, A: Allocator, G: Growth> Eq for CVec<T, A, G> {}
 
-impl<T: std::hash::Hash> std::hash::Hash for CVec<T> {
+impl<T: std::hash::Hash, A: Allocator, G: Growth> std::hash::Hash for CVec<T, A, G> {
 	fn hash<H: std::hash::Hasher>(&self, h: &mut H) {
 		(**self).hash(h);
 	}
 }
 
-impl<T: PartialOrd> PartialOrd for CVec<T> {
+impl<T: PartialOrd, A: Allocator, G: Growth> PartialOrd for CVec<T, A, G> {
 	fn partial_cmp(&self, rhs: &Self) -> Option<std::cmp::Ordering> {
 		(**self).partial_cmp(&**rhs)
 	}
@@ -399,17 +814,101 @@ impl<T: PartialOrd> PartialOrd for CVec<T> {
 	fn ge(&self, rhs: &Self) -> bool { **self >= **rhs }
 }
 
-impl<T: Ord> Ord for CVec<T> {
+impl<T: Ord, A: Allocator, G: Growth> Ord for CVec<T, A, G> {
 	fn cmp(&self, rhs: &Self) -> std::cmp::Ordering {
 		(**self).cmp(&**rhs)
 	}
 }
 
-unsafe impl<T> super::vec_ext::VecExt<T> for CVec<T> {
+unsafe impl<T, A: Allocator, G: Growth> super::vec_ext::VecExt<T> for CVec<T, A, G> {
 	fn clear(&mut self) { CVec::clear(self); }
 	fn pop(&mut self) -> Option<T> { CVec::pop(self) }
 }
 
+impl<A: Allocator, G: Growth> io::Write for CVec<u8, A, G> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.extend_copy_slice(buf);
+		Ok(buf.len())
+	}
+
+	fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+		self.extend_copy_slice(buf);
+		Ok(())
+	}
+
+	fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+impl<A: Allocator, G: Growth> CVec<u8, A, G> {
+	// appends everything `read` has left, returning how many bytes that was
+	pub fn read_from(&mut self, mut read: impl io::Read) -> io::Result<usize> {
+		let start = self.len();
+		let mut buf = [0; 8192];
+		loop {
+			match read.read(&mut buf) {
+				Ok(0) => return Ok(self.len() - start),
+				Ok(n) => self.extend_copy_slice(&buf[.. n]),
+				Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+				Err(e) => return Err(e),
+			}
+		}
+	}
+}
+
+// reads the bytes of a `CVec` it owns
+pub struct Cursor<A: Allocator = Global, G: Growth = Tight> {
+	vec: CVec<u8, A, G>,
+	pos: usize,
+}
+
+impl<A: Allocator + Clone, G: Growth> Clone for Cursor<A, G> {
+	fn clone(&self) -> Self { Self { vec: self.vec.clone(), pos: self.pos } }
+}
+
+impl<A: Allocator + Default, G: Growth> Default for Cursor<A, G> {
+	fn default() -> Self { Self::new(CVec::default()) }
+}
+
+impl<A: Allocator, G: Growth> std::fmt::Debug for Cursor<A, G> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Cursor").field("vec", &self.vec).field("pos", &self.pos).finish()
+	}
+}
+
+impl<A: Allocator, G: Growth> Cursor<A, G> {
+	pub fn new(vec: CVec<u8, A, G>) -> Self { Self { vec, pos: 0 } }
+
+	pub fn position(&self) -> usize { self.pos }
+
+	pub fn set_position(&mut self, pos: usize) { self.pos = pos.min(self.vec.len()); }
+
+	pub fn remaining(&self) -> &[u8] { &self.vec[self.pos ..] }
+
+	pub fn get_ref(&self) -> &CVec<u8, A, G> { &self.vec }
+
+	pub fn into_inner(self) -> CVec<u8, A, G> { self.vec }
+}
+
+impl<A: Allocator, G: Growth> io::Read for Cursor<A, G> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let n = io::Read::read(&mut self.remaining(), buf)?;
+		self.pos += n;
+		Ok(n)
+	}
+
+	fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+		io::Read::read_exact(&mut self.remaining(), buf)?;
+		self.pos += buf.len();
+		Ok(())
+	}
+}
+
+impl<A: Allocator, G: Growth> io::BufRead for Cursor<A, G> {
+	fn fill_buf(&mut self) -> io::Result<&[u8]> { Ok(self.remaining()) }
+
+	fn consume(&mut self, amt: usize) { self.pos = (self.pos + amt).min(self.vec.len()); }
+}
+
 #[test]
 fn test() {
 	let mut vec;
@@ -436,40 +935,514 @@ fn test() {
 	vec.extend(I(0..100));
 }
 
+#[test]
+fn vec_api() {
+	use std::{cell::Cell, panic::{catch_unwind, AssertUnwindSafe}};
+
+	let mut vec = (0..10).collect::<CVec<i32>>();
+	assert_eq!(vec.drain(2..5).collect::<Vec<_>>(), [2, 3, 4]);
+	assert_eq!(vec[..], [0, 1, 5, 6, 7, 8, 9]);
+	assert_eq!(vec.cap(), 8);
+	assert_eq!(vec.drain(..=1).rev().collect::<Vec<_>>(), [1, 0]);
+	assert_eq!(vec.cap(), 8);
+	vec.drain(3..).next();
+	assert_eq!(vec[..], [5, 6, 7]);
+	assert_eq!(vec.cap(), 4);
+	assert_eq!(vec.drain(1..1).len(), 0);
+
+	vec.insert(0, 4);
+	vec.insert(4, 8);
+	vec.insert(2, 0);
+	assert_eq!(vec[..], [4, 5, 0, 6, 7, 8]);
+	assert_eq!(vec.remove(2), 0);
+	assert_eq!(vec.remove(4), 8);
+	assert_eq!(vec.cap(), 4);
+
+	let tail = vec.split_off(1);
+	assert_eq!((&vec[..], &tail[..]), (&[4][..], &[5, 6, 7][..]));
+	assert_eq!((vec.cap(), tail.cap()), (1, 4));
+	vec.extend(tail);
+
+	assert_eq!(vec.splice(1..3, 10..15).collect::<Vec<_>>(), [5, 6]);
+	assert_eq!(vec[..], [4, 10, 11, 12, 13, 14, 7]);
+	vec.retain(|&elem| elem % 2 == 0);
+	assert_eq!(vec[..], [4, 10, 12, 14]);
+	vec.truncate(2);
+	assert_eq!((&vec[..], vec.cap()), (&[4, 10][..], 2));
+	vec.truncate(3);
+	assert_eq!(vec.len(), 2);
+
+	let mut vec = CVec::from_copy(&[1, 1, 2, 3, 3, 3, 1, 4, 4]);
+	vec.dedup();
+	assert_eq!(vec[..], [1, 2, 3, 1, 4]);
+	vec.dedup_by_key(|elem| *elem / 2);
+	assert_eq!(vec[..], [1, 2, 1, 4]);
+
+	let mut vec = CVec::from_copy(&[1, 2, 3]);
+	std::mem::forget(vec.drain(1..));
+	assert!(vec.is_empty());
+
+	// drops happen exactly once, even if a closure or a drop panics
+	struct D<'a>(&'a Cell<usize>, bool);
+	impl Drop for D<'_> {
+		fn drop(&mut self) {
+			self.0.set(self.0.get() + 1);
+			if self.1 { panic!("D") }
+		}
+	}
+
+	let drops = Cell::new(0);
+	let mut vec = (0..10).map(|_| D(&drops, false)).collect::<CVec<_>>();
+	let mut seen = 0;
+	catch_unwind(AssertUnwindSafe(|| vec.retain(|_| {
+		seen += 1;
+		if seen == 6 { panic!("retain") }
+		seen % 2 == 0
+	}))).unwrap_err();
+	assert_eq!((drops.get(), vec.len()), (3, 7));
+	vec.dedup_by(|_, _| true);
+	assert_eq!((drops.get(), vec.len()), (9, 1));
+
+	vec.extend((0..4).map(|i| D(&drops, i == 1)));
+	catch_unwind(AssertUnwindSafe(|| { vec.drain(1..).next_back(); })).unwrap_err();
+	assert_eq!((drops.get(), vec.len()), (13, 1));
+}
+
+// ported from the std `Vec` tests that cover the same operations
+#[test]
+fn vec_std() {
+	use std::{cell::Cell, panic::{catch_unwind, AssertUnwindSafe}};
+
+	fn cvec(elems: &[i32]) -> CVec<i32> { CVec::from_copy(elems) }
+
+	// test_drain_items, test_drain_items_reverse, test_drain_range
+	let mut vec = cvec(&[1, 2, 3]);
+	assert_eq!(vec.drain(..).collect::<Vec<_>>(), [1, 2, 3]);
+	assert!(vec.is_empty());
+	let mut vec = cvec(&[1, 2, 3]);
+	assert_eq!(vec.drain(..).rev().collect::<Vec<_>>(), [3, 2, 1]);
+	assert!(vec.is_empty());
+
+	let mut vec = cvec(&[1, 2, 3, 4, 5]);
+	for _ in vec.drain(4..) {}
+	assert_eq!(vec[..], [1, 2, 3, 4]);
+	let mut vec = (1..=5).map(|i| i.to_string()).collect::<CVec<_>>();
+	for _ in vec.drain(1..4) {}
+	assert_eq!(vec[..], ["1", "5"]);
+	let mut vec = (1..=5).map(|i| i.to_string()).collect::<CVec<_>>();
+	for _ in vec.drain(1..4).rev() {}
+	assert_eq!(vec[..], ["1", "5"]);
+	let mut vec = vec![(); 5].into_iter().collect::<CVec<_>>();
+	for _ in vec.drain(1..4).rev() {}
+	assert_eq!(vec.len(), 2);
+
+	// test_drain_inclusive_range
+	let mut vec = "abcde".chars().collect::<CVec<_>>();
+	assert_eq!(vec.drain(1..=3).collect::<String>(), "bcd");
+	assert_eq!(vec[..], ['a', 'e']);
+	let mut vec = (0..=5).map(|i| i.to_string()).collect::<CVec<_>>();
+	assert_eq!(vec.drain(0..=5).count(), 6);
+	assert!(vec.is_empty());
+	let mut vec = (0..=5).map(|i| i.to_string()).collect::<CVec<_>>();
+	assert_eq!(vec.drain(1..=4).count(), 4);
+	assert_eq!(vec[..], ["0", "5"]);
+
+	// test_drain_out_of_bounds, test_drain_start_overflow, test_drain_end_overflow
+	let mut vec = cvec(&[1, 2, 3, 4, 5]);
+	catch_unwind(AssertUnwindSafe(|| { vec.drain(5..6); })).unwrap_err();
+	catch_unwind(AssertUnwindSafe(|| { vec.drain(3..2); })).unwrap_err();
+	catch_unwind(AssertUnwindSafe(|| {
+		vec.drain((ops::Bound::Excluded(usize::max_value()), ops::Bound::Included(0)));
+	})).unwrap_err();
+	catch_unwind(AssertUnwindSafe(|| { vec.drain(..=usize::max_value()); })).unwrap_err();
+	assert_eq!(vec[..], [1, 2, 3, 4, 5]);
+
+	// a partly consumed drain drops the rest of its range
+	let mut vec = cvec(&[0, 1, 2, 3, 4, 5, 6]);
+	let mut drain = vec.drain(1..6);
+	assert_eq!((drain.next(), drain.next_back()), (Some(1), Some(5)));
+	drop(drain);
+	assert_eq!(vec[..], [0, 6]);
+
+	struct D<'a>(&'a Cell<usize>, u32, bool);
+	impl Drop for D<'_> {
+		fn drop(&mut self) {
+			self.0.set(self.0.get() + 1);
+			if self.2 { panic!("D") }
+		}
+	}
+
+	// test_drain_leak: a panicking drop leaves the elements around the range
+	let drops = Cell::new(0);
+	let mut vec = (0..7).map(|i| D(&drops, i, i == 4)).collect::<CVec<_>>();
+	catch_unwind(AssertUnwindSafe(|| { vec.drain(2..=5); })).unwrap_err();
+	assert_eq!(drops.get(), 4);
+	assert_eq!(vec.iter().map(|d| d.1).collect::<Vec<_>>(), [0, 1, 6]);
+
+	// test_vec_truncate_drop, test_vec_truncate_fail
+	let drops = Cell::new(0);
+	let mut vec = (0..5).map(|i| D(&drops, i, false)).collect::<CVec<_>>();
+	vec.truncate(5);
+	assert_eq!(drops.get(), 0);
+	vec.truncate(2);
+	assert_eq!(drops.get(), 3);
+	vec.truncate(0);
+	assert_eq!(drops.get(), 5);
+
+	let drops = Cell::new(0);
+	let mut vec = (0..3).map(|i| D(&drops, i, i == 2)).collect::<CVec<_>>();
+	catch_unwind(AssertUnwindSafe(|| vec.truncate(1))).unwrap_err();
+	assert_eq!((drops.get(), vec.len(), vec[0].1), (2, 1, 0));
+	drop(vec);
+	assert_eq!(drops.get(), 3);
+
+	// test_insert, test_remove and their out of bounds panics
+	let mut vec = cvec(&[1, 2, 3]);
+	vec.insert(3, 4);
+	vec.insert(0, 0);
+	assert_eq!(vec[..], [0, 1, 2, 3, 4]);
+	catch_unwind(AssertUnwindSafe(|| vec.insert(6, 5))).unwrap_err();
+	assert_eq!(vec.remove(4), 4);
+	assert_eq!(vec.remove(0), 0);
+	catch_unwind(AssertUnwindSafe(|| vec.remove(3))).unwrap_err();
+	assert_eq!(vec[..], [1, 2, 3]);
+	assert_eq!(vec.swap_remove(0), 1);
+	assert_eq!(vec[..], [3, 2]);
+
+	// test_split_off, test_split_off_take_all
+	let mut vec = cvec(&[1, 2, 3, 4, 5, 6]);
+	let tail = vec.split_off(4);
+	assert_eq!((&vec[..], &tail[..]), (&[1, 2, 3, 4][..], &[5, 6][..]));
+	let tail = vec.split_off(4);
+	assert_eq!((vec.len(), tail.len()), (4, 0));
+	let all = vec.split_off(0);
+	assert_eq!((vec.len(), &all[..]), (0, &[1, 2, 3, 4][..]));
+	catch_unwind(AssertUnwindSafe(|| { vec.split_off(1); })).unwrap_err();
+
+	// test_splice, test_splice_inclusive_range, test_splice_unbounded, test_splice_forget
+	let mut vec = cvec(&[1, 2, 3, 4, 5]);
+	vec.splice(2..4, [10, 11, 12].iter().cloned());
+	assert_eq!(vec[..], [1, 2, 10, 11, 12, 5]);
+	vec.splice(1..3, Some(20));
+	assert_eq!(vec[..], [1, 20, 11, 12, 5]);
+	let removed = vec.splice(1..=2, [30, 31, 32].iter().cloned()).collect::<Vec<_>>();
+	assert_eq!((&vec[..], &removed[..]), (&[1, 30, 31, 32, 12, 5][..], &[20, 11][..]));
+	let removed = vec.splice(.., None).collect::<Vec<_>>();
+	assert_eq!((vec.len(), &removed[..]), (0, &[1, 30, 31, 32, 12, 5][..]));
+	let mut vec = cvec(&[1, 2, 3, 4, 5]);
+	std::mem::forget(vec.splice(2..4, [10, 11, 12].iter().cloned()));
+	assert_eq!(vec[..], [1, 2, 10, 11, 12, 5]);
+	catch_unwind(AssertUnwindSafe(|| { vec.splice(5..8, None); })).unwrap_err();
+
+	// test_retain, test_retain_pred_panic_with_hole
+	let mut vec = cvec(&[1, 2, 3, 4]);
+	vec.retain(|&x| x % 2 == 0);
+	assert_eq!(vec[..], [2, 4]);
+	let mut vec = cvec(&[1, 2, 3, 4]);
+	vec.retain_mut(|x| { *x += 1; *x % 3 != 0 });
+	assert_eq!(vec[..], [2, 4, 5]);
+
+	let drops = Cell::new(0);
+	let mut vec = (0..6).map(|i| D(&drops, i, false)).collect::<CVec<_>>();
+	catch_unwind(AssertUnwindSafe(|| vec.retain(|d| {
+		if d.1 == 3 { panic!("retain") }
+		d.1 % 2 == 0
+	}))).unwrap_err();
+	assert_eq!(drops.get(), 1);
+	assert_eq!(vec.iter().map(|d| d.1).collect::<Vec<_>>(), [0, 2, 3, 4, 5]);
+
+	// test_dedup, test_dedup_by_key, test_dedup_by, test_dedup_unique
+	let mut vec = cvec(&[1, 1, 2, 3, 3, 3, 4, 1, 1]);
+	vec.dedup();
+	assert_eq!(vec[..], [1, 2, 3, 4, 1]);
+	let mut vec = cvec(&[10, 11, 12, 20, 30, 31, 12]);
+	vec.dedup_by_key(|x| *x / 10);
+	assert_eq!(vec[..], [10, 20, 30, 12]);
+	let mut vec = ["foo", "bar", "Bar", "baz", "bar"].iter().cloned().collect::<CVec<_>>();
+	vec.dedup_by(|a, b| a.eq_ignore_ascii_case(b));
+	assert_eq!(vec[..], ["foo", "bar", "baz", "bar"]);
+	let mut vec = cvec(&[]);
+	vec.dedup();
+	assert!(vec.is_empty());
+
+	let mut vec = (0..4).map(|i| Box::new(i / 2)).collect::<CVec<_>>();
+	vec.dedup();
+	assert_eq!(vec.iter().map(|b| **b).collect::<Vec<_>>(), [0, 1]);
+}
+
+#[test]
+fn vec_conversions() {
+	let mut vec = Vec::with_capacity(4);
+	vec.extend(["a", "b", "c"].iter().map(|s| s.to_string()));
+	let data = vec.as_ptr();
+	let cvec = CVec::from(vec);
+	assert_eq!((cvec.as_ptr() as *const _, cvec.concat()), (data, "abc".to_string()));
+
+	let vec = Vec::from(cvec);
+	assert_eq!((vec.as_ptr(), vec.capacity()), (data, 4));
+
+	let cvec = CVec::from((0..5).collect::<Vec<_>>());
+	assert_eq!((&cvec[..], cvec.cap()), (&[0, 1, 2, 3, 4][..], 8));
+	assert_eq!(CVec::from(Vec::<u8>::with_capacity(3)).cap(), 0);
+	assert!(Vec::from(CVec::<u8>::new()).is_empty());
+
+	let mut vec = vec![1, 2];
+	let mut cvec = CVec::new();
+	cvec.extend_append_vec(&mut vec);
+	vec.push(3);
+	cvec.extend_append_vec(&mut vec);
+	assert_eq!((&cvec[..], vec.len()), (&[1, 2, 3][..], 0));
+	vec.extend_append_cvec(&mut cvec);
+	cvec.push(4);
+	vec.extend_append_cvec(&mut cvec);
+	assert_eq!((&vec[..], cvec.len()), (&[1, 2, 3, 4][..], 0));
+}
+
+// small enough to run under Miri, which checks the layouts passed to the allocator
+#[test]
+fn soundness() {
+	use std::{cell::Cell, panic::{catch_unwind, AssertUnwindSafe}, rc::Rc};
+
+	let mut vec = CVec::new();
+	for i in 0..33 {
+		vec.push(Box::new(i));
+		assert_eq!(vec.cap(), vec.len().next_power_of_two());
+	}
+	for i in (0..33).rev() {
+		assert_eq!(*vec.pop().unwrap(), i);
+	}
+	assert!(vec.pop().is_none());
+
+	let mut units = CVec::new();
+	for _ in 0..100 { units.push(()); }
+	units.extend(std::iter::repeat(()).take(5));
+	units.truncate(50);
+	assert_eq!((units.len(), units.pop()), (50, Some(())));
+	assert_eq!(units.into_iter().count(), 49);
+
+	struct Hint(std::ops::Range<u8>, (usize, Option<usize>));
+	impl Iterator for Hint {
+		type Item = u8;
+		fn next(&mut self) -> Option<u8> { self.0.next() }
+		fn size_hint(&self) -> (usize, Option<usize>) { self.1 }
+	}
+
+	for &(len, hint) in &[(0, 0), (3, 0), (3, 20), (9, 3), (0, 5)] {
+		let mut vec = CVec::from_copy(&[0u8; 3]);
+		vec.extend(Hint(0..len, (hint, None)));
+		assert_eq!(vec.len(), 3 + len as usize);
+	}
+
+	let counter = Rc::new(());
+	let mut vec = CVec::new();
+	vec.resize(3, counter.clone());
+	let mut calls = 0;
+	catch_unwind(AssertUnwindSafe(|| vec.resize_with(20, || {
+		calls += 1;
+		if calls == 7 { panic!("resize_with") }
+		counter.clone()
+	}))).unwrap_err();
+	assert_eq!((vec.len(), vec.cap()), (9, 16));
+	assert_eq!(Rc::strong_count(&counter), 10);
+
+	catch_unwind(AssertUnwindSafe(|| vec.extend((0..5).map(|i| {
+		if i == 4 { panic!("extend") }
+		counter.clone()
+	})))).unwrap_err();
+	assert_eq!((vec.len(), vec.cap()), (13, 16));
+	vec.truncate(2);
+	assert_eq!(Rc::strong_count(&counter), 3);
+
+	struct D<'a>(&'a Cell<usize>, bool);
+	impl Drop for D<'_> {
+		fn drop(&mut self) {
+			self.0.set(self.0.get() + 1);
+			if self.1 { panic!("D") }
+		}
+	}
+
+	let drops = Cell::new(0);
+	let vec = (0..6).map(|i| D(&drops, i == 2)).collect::<CVec<_>>();
+	let mut iter = vec.into_iter();
+	iter.next();
+	catch_unwind(AssertUnwindSafe(|| drop(iter))).unwrap_err();
+	assert_eq!(drops.get(), 6);
+
+	let mut iter = CVec::from(vec![Box::new(1), Box::new(2), Box::new(3)]).into_iter();
+	assert_eq!((*iter.next().unwrap(), iter.len()), (1, 2));
+}
+
+#[test]
+fn hysteresis() {
+	let mut vec = HCVec::<u32>::default();
+	assert_eq!(size_of::<HCVec<u32>>(), size_of::<CVec<u32>>());
+
+	vec.extend(0..16);
+	vec.push(16);
+	let data = vec.as_ptr();
+	for i in 0..20 {
+		vec.pop();
+		vec.push(i);
+	}
+	assert_eq!((vec.len(), vec.cap(), vec.as_ptr()), (17, 32, data));
+
+	vec.truncate(8);
+	assert_eq!((&vec[..], vec.cap()), (&[0, 1, 2, 3, 4, 5, 6, 7][..], 32));
+	vec.pop();
+	assert_eq!((vec.len(), vec.cap()), (7, 16));
+
+	vec.reserve(100);
+	assert_eq!(vec.cap(), 128);
+	let data = vec.as_ptr();
+	vec.extend(0..100);
+	assert_eq!((vec.len(), vec.cap(), vec.as_ptr()), (107, 128, data));
+	assert_eq!(vec.drain(5..).len(), 102);
+	assert_eq!((vec.len(), vec.cap()), (5, 16));
+	vec.clear();
+	assert_eq!(vec.cap(), 0);
+
+	// pushing into a reservation keeps it
+	vec.reserve(100);
+	let data = vec.as_ptr();
+	for i in 0..100 { vec.push(i); }
+	vec.insert(0, 100);
+	assert_eq!((vec.len(), vec.cap(), vec.as_ptr()), (101, 128, data));
+	vec.truncate(1);
+	vec.extend(0..2);
+	assert_eq!((vec.len(), vec.cap()), (3, 4));
+	vec.reserve(30);
+	vec.extend(Some(0));
+	assert_eq!((vec.len(), vec.cap()), (4, 64));
+
+	let mut vec = CVec::from_copy(&[1u8, 2, 3]);
+	vec.reserve(100);
+	assert_eq!(vec.cap(), 4);
+
+	let max = 1 << Hysteresis::SHIFT;
+	assert_eq!(Hysteresis::len(Hysteresis::word(max - 1, max)), max - 1);
+	std::panic::catch_unwind(|| Hysteresis::word(max, max)).unwrap_err();
+}
+
+#[test]
+fn allocator() {
+	use std::{cell::Cell, rc::Rc, alloc::AllocError};
+
+	#[derive(Clone, Default)]
+	struct Counting(Rc<Cell<isize>>);
+
+	unsafe impl Allocator for Counting {
+		fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+			self.0.set(self.0.get() + layout.size() as isize);
+			Global.allocate(layout)
+		}
+
+		unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+			self.0.set(self.0.get() - layout.size() as isize);
+			Global.deallocate(ptr, layout)
+		}
+	}
+
+	let alloc = Counting::default();
+	let mut vec = CVec::new_in(alloc.clone());
+	vec.extend(0..100u32);
+	assert_eq!(alloc.0.get(), 128 * 4);
+	vec.resize(5, 0);
+	assert_eq!(alloc.0.get(), 8 * 4);
+
+	let mut other = vec.clone();
+	other.push(1);
+	assert_eq!(alloc.0.get(), 16 * 4);
+	assert_eq!(other.into_iter().sum::<u32>(), (0..5).sum::<u32>() + 1);
+	assert_eq!(alloc.0.get(), 8 * 4);
+
+	let mut global = CVec::from_copy(&[7, 8]);
+	vec.extend_append_cvec(&mut global);
+	assert!(global.is_empty());
+	assert_eq!(vec[..], [0, 1, 2, 3, 4, 7, 8]);
+
+	let (data, len, alloc2) = vec.into_raw_with_allocator();
+	let vec = unsafe { CVec::from_raw_in(data, len, alloc2) };
+	assert!(Rc::ptr_eq(&vec.allocator().0, &alloc.0));
+	drop(vec);
+	assert_eq!(alloc.0.get(), 0);
+}
+
+#[test]
+fn io() {
+	use std::io::{BufRead, Read, Write};
+
+	let mut vec = CVec::new();
+	write!(vec, "{} {}\nline", 1, "two").unwrap();
+	vec.write_all(&[b'!'; 3]).unwrap();
+	assert_eq!((&vec[..], vec.cap()), (&b"1 two\nline!!!"[..], 16));
+
+	let mut cursor = Cursor::new(vec);
+	let mut line = String::new();
+	cursor.read_line(&mut line).unwrap();
+	assert_eq!((&line[..], cursor.position()), ("1 two\n", 6));
+	let mut buf = [0; 4];
+	cursor.read_exact(&mut buf).unwrap();
+	assert_eq!((&buf, cursor.remaining()), (b"line", &b"!!!"[..]));
+	assert!(cursor.read_exact(&mut buf).is_err());
+	assert_eq!(cursor.read(&mut buf).unwrap(), 3);
+
+	let mut vec = CVec::from_copy(b"x");
+	assert_eq!(vec.read_from(&[7u8; 10000][..]).unwrap(), 10000);
+	assert_eq!((vec.len(), vec.cap(), vec[10000]), (10001, 16384, 7));
+
+	#[cfg(feature = "serde")] {
+		use crate::bytes::{BytesSer, BytesDe};
+
+		let mut ser: BytesSer = BytesSer::new();
+		ser.serialize(&("str", vec![1u32, 2], Some(3u8))).unwrap();
+		let mut out = CVec::new();
+		ser.slices().try_for_each(|slice| out.write_all(slice)).unwrap();
+		let mut cursor = Cursor::new(out);
+		let value = BytesDe::new(&mut cursor).deserialize::<(String, Vec<u32>, Option<u8>)>().unwrap();
+		assert_eq!((&value.0[..], &value.1[..], value.2, cursor.remaining()), ("str", &[1, 2][..], Some(3), &[][..]));
+
+		let mut vec = HCVec::<u32>::default();
+		vec.extend(0..5);
+		let mut ser: BytesSer = BytesSer::new();
+		ser.serialize(&vec).unwrap();
+		let data = ser.bytes().collect::<Vec<u8>>();
+		assert_eq!(BytesDe::new(&mut &data[..]).deserialize::<HCVec<u32>>().unwrap()[..], vec[..]);
+	}
+}
+
 #[cfg(feature = "serde")]
-impl<T: serde::Serialize> serde::Serialize for CVec<T> {
+impl<T: serde::Serialize, A: Allocator, G: Growth> serde::Serialize for CVec<T, A, G> {
 	fn serialize<S>(&self, ser: S) -> Result<S::Ok, S::Error> where
 		S: serde::Serializer,
 	{
-		use serde::ser::SerializeSeq;
-		let mut ser = ser.serialize_seq(Some(self.len()))?;
-		for value in self {
-			ser.serialize_element(value)?;
-		}
-		ser.end()
+		ser.collect_seq(self)
 	}
 }
 
 #[cfg(feature = "serde")]
-impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for CVec<T> {
+impl<'de, T, A, G> serde::Deserialize<'de> for CVec<T, A, G> where
+	T: serde::Deserialize<'de>,
+	A: Allocator + Default,
+	G: Growth,
+{
 	fn deserialize<D>(de: D) -> Result<Self, D::Error> where
 		D: serde::Deserializer<'de>,
 	{
-		use std::marker::PhantomData;
-
-		struct Visitor<T>(PhantomData<T>);
+		struct Visitor<T, A, G>(PhantomData<(T, A, G)>);
 
-		impl<'de, T> serde::de::Visitor<'de> for Visitor<T> where
+		impl<'de, T, A, G> serde::de::Visitor<'de> for Visitor<T, A, G> where
 			T: serde::Deserialize<'de>,
+			A: Allocator + Default,
+			G: Growth,
 		{
-			type Value = CVec<T>;
+			type Value = CVec<T, A, G>;
 
 			fn expecting(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
 				fmt.write_str("CVec sequence")
 			}
 
-			fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error> where
-				A: serde::de::SeqAccess<'de>,
+			fn visit_seq<S>(self, seq: S) -> Result<Self::Value, S::Error> where
+				S: serde::de::SeqAccess<'de>,
 			{
 				struct Iter<'err, 'de, A: serde::de::SeqAccess<'de>, T> {
 					size_hint: (usize, Option<usize>),
@@ -497,9 +1470,8 @@ impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for CVec<T> {
 
 				let mut opt_error = None;
 
-				let vec = <CVec<T> as std::iter::FromIterator<T>>::from_iter(
-					Iter { size_hint, seq, error: &mut opt_error, _item: PhantomData },
-				);
+				let mut vec = CVec::new_in(A::default());
+				vec.extend(Iter { size_hint, seq, error: &mut opt_error, _item: PhantomData });
 
 				if let Some(error) = opt_error {
 					Err(error)
@@ -509,7 +1481,7 @@ impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for CVec<T> {
 			}
 		}
 
-		de.deserialize_seq(Visitor::<T>(PhantomData))
+		de.deserialize_seq(Visitor::<T, A, G>(PhantomData))
 	}
 }
 
@@ -31,3 +31,17 @@ impl<T> DerefMut for DerefInner<T> where
 	fn deref_mut(&mut self) -> &mut Self::Target { &mut **self.0 }
 }
 
+
+#[cfg(feature = "serde")]
+impl<T: ?Sized + serde::Serialize> serde::Serialize for DerefInner<T> {
+	fn serialize<S: serde::Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+		self.0.serialize(ser)
+	}
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for DerefInner<T> {
+	fn deserialize<D: serde::Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+		T::deserialize(de).map(Self)
+	}
+}
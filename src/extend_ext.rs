@@ -22,8 +22,16 @@ impl<T> ExtendExt<T> for Vec<T> {
 	fn extend_append_vec(&mut self, rhs: &mut Vec<T>) { self.append(rhs); }
 
 	fn extend_append_cvec(&mut self, rhs: &mut crate::cvec::CVec<T>) {
-		//TODO optimize this by copying memory
-		self.extend_trusted_len(rhs.drain(..));
+		if self.is_empty() {
+			*self = std::mem::take(rhs).into();
+		} else {
+			self.reserve(rhs.len());
+			unsafe {
+				std::ptr::copy_nonoverlapping(rhs.as_ptr(), self.as_mut_ptr().add(self.len()), rhs.len());
+				self.set_len(self.len() + rhs.len());
+			}
+			rhs.clear_forget();
+		}
 	}
 }
 
@@ -14,6 +14,8 @@
 	half_open_range_patterns,
 	exclusive_range_pattern,
 	option_result_unwrap_unchecked,
+	min_specialization,
+	unsize,
 )]
 
 #![deny(unused_must_use)]
@@ -31,6 +33,9 @@ pub mod on_drop;
 pub mod maps;
 pub mod range_ext;
 //pub mod thin;
+pub mod thin_vec;
+pub mod small_cvec;
+pub mod seg_vec;
 pub mod unreach;
 //pub mod alloc_vec;
 //pub mod dedup_vec;
@@ -53,7 +58,7 @@ pub mod rev_ord;
 
 #[allow(deprecated)]
 pub use crate::{
-	cvec::CVec,
+	cvec::{CVec, HCVec},
 	//sbox::SBox,
 	entry_ext::*,
 	just_hash::*,
@@ -65,6 +70,9 @@ pub use crate::{
 	rev_ord::*,
 	range_ext::*,
 	//thin::*,
+	thin_vec::ThinVec,
+	small_cvec::SmallCVec,
+	seg_vec::SegVec,
 	unreach::*,
 	derefs::*,
 	by_ptr::*,
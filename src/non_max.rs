@@ -58,3 +58,26 @@ impl<I> std::fmt::Debug for NonMax<I> where
 	}
 }
 
+
+#[cfg(feature = "serde")]
+impl<I> serde::Serialize for NonMax<I> where
+	I: num_traits::Bounded + Eq + Copy + serde::Serialize,
+{
+	fn serialize<S: serde::Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+		serde::Serialize::serialize(&self.get(), ser)
+	}
+}
+
+#[cfg(feature = "serde")]
+impl<'de, I> serde::Deserialize<'de> for NonMax<I> where
+	I: num_traits::Bounded + Eq + Copy + serde::Deserialize<'de>,
+{
+	fn deserialize<D: serde::Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+		match <Option<I> as serde::Deserialize>::deserialize(de)? {
+			Some(n) if n == I::max_value() => Err(serde::de::Error::custom(
+				"NonMax can't hold its type's maximum value"
+			)),
+			opt => Ok(opt.into()),
+		}
+	}
+}
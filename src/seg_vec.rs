@@ -0,0 +1,201 @@
+use {
+	crate::atomic,
+	std::{cell::UnsafeCell, mem::MaybeUninit, ptr},
+};
+
+const SEGMENTS: usize = usize::BITS as usize;
+
+struct Slot<T> {
+	ready: atomic::bool,
+	value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// an append-only vector whose elements never move, so they can be borrowed
+// while other threads push; segment `k` holds `2^k` elements, like the
+// capacities `CVec` goes through, and is allocated by the first push into it
+pub struct SegVec<T> {
+	segments: [atomic::Ptr<Slot<T>>; SEGMENTS],
+	claimed: atomic::usize,
+}
+
+// the segment element `index` is in, and its position there
+fn locate(index: usize) -> (usize, usize) {
+	let pos = index + 1;
+	let segment = SEGMENTS - 1 - pos.leading_zeros() as usize;
+	(segment, pos - (1 << segment))
+}
+
+impl<T> SegVec<T> {
+	pub fn new() -> Self {
+		Self {
+			segments: std::array::from_fn(|_| atomic::Ptr::new(ptr::null_mut())),
+			claimed: atomic::usize::new(0),
+		}
+	}
+
+	// how many elements have been pushed, including ones still being written
+	pub fn len(&self) -> usize { self.claimed.load(atomic::Acquire) }
+
+	pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+	fn slot(&self, index: usize) -> Option<&Slot<T>> {
+		// also keeps `locate` from overflowing on `usize::MAX`
+		if index >= self.len() { return None; }
+		let (segment, pos) = locate(index);
+		let slots = self.segments[segment].load(atomic::Acquire);
+		if slots.is_null() { None } else { Some(unsafe { &*slots.add(pos) }) }
+	}
+
+	// threads that push into a segment before it's published each build a
+	// copy of it and all but one free theirs again; only pushes in that window
+	// race, so the waste is short-lived, and cheaper than making pushers wait
+	fn alloc_segment(&self, segment: usize) -> *mut Slot<T> {
+		let slots = &self.segments[segment];
+		let current = slots.load(atomic::Acquire);
+		if !current.is_null() { return current; }
+
+		let new = Box::into_raw((0 .. 1usize << segment).map(|_| Slot {
+			ready: atomic::bool::new(false),
+			value: UnsafeCell::new(MaybeUninit::uninit()),
+		}).collect::<Box<[Slot<T>]>>()) as *mut Slot<T>;
+
+		match slots.compare_exchange(ptr::null_mut(), new, atomic::AcqRel, atomic::Acquire) {
+			Ok(_) => new,
+			Err(current) => {
+				// another thread allocated the segment first
+				unsafe { drop(Box::from_raw(ptr::slice_from_raw_parts_mut(new, 1 << segment))); }
+				current
+			}
+		}
+	}
+
+	// appends `value`, returning its index and a reference that stays valid for
+	// as long as the vector
+	pub fn push(&self, value: T) -> (usize, &T) {
+		let index = self.claimed.fetch_add(1, atomic::AcqRel);
+		assert!(index < usize::MAX, "SegVec is full");
+
+		let (segment, pos) = locate(index);
+		unsafe {
+			let slot = &*self.alloc_segment(segment).add(pos);
+			let value = (*slot.value.get()).write(value);
+			slot.ready.store(true, atomic::Release);
+			(index, value)
+		}
+	}
+
+	// `None` if `index` hasn't been pushed yet, or is still being written
+	pub fn get(&self, index: usize) -> Option<&T> {
+		let slot = self.slot(index)?;
+		if slot.ready.load(atomic::Acquire) {
+			Some(unsafe { (*slot.value.get()).assume_init_ref() })
+		} else {
+			None
+		}
+	}
+
+	pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+		let slot = self.slot(index)?;
+		if slot.ready.load(atomic::Relaxed) {
+			Some(unsafe { (*slot.value.get()).assume_init_mut() })
+		} else {
+			None
+		}
+	}
+
+	// skips elements that are still being written
+	pub fn iter(&self) -> impl '_ + Iterator<Item = &T> {
+		(0 .. self.len()).filter_map(move |index| self.get(index))
+	}
+}
+
+impl<T> Drop for SegVec<T> {
+	fn drop(&mut self) {
+		for (segment, slots) in self.segments.iter_mut().enumerate() {
+			let slots = *slots.get_mut();
+			if slots.is_null() { continue; }
+
+			unsafe {
+				let mut slots = Box::from_raw(ptr::slice_from_raw_parts_mut(slots, 1 << segment));
+				for slot in slots.iter_mut() {
+					if *slot.ready.get_mut() {
+						slot.value.get_mut().assume_init_drop();
+					}
+				}
+			}
+		}
+	}
+}
+
+impl<T> Default for SegVec<T> {
+	fn default() -> Self { Self::new() }
+}
+
+unsafe impl<T: Send> Send for SegVec<T> {}
+unsafe impl<T: Send + Sync> Sync for SegVec<T> {}
+
+impl<T> std::ops::Index<usize> for SegVec<T> {
+	type Output = T;
+	fn index(&self, index: usize) -> &T {
+		self.get(index).unwrap_or_else(|| panic!("no element at index {}", index))
+	}
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for SegVec<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_list().entries(self.iter()).finish()
+	}
+}
+
+impl<T> Extend<T> for SegVec<T> {
+	fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+		iter.into_iter().for_each(|value| { self.push(value); });
+	}
+}
+
+impl<T> std::iter::FromIterator<T> for SegVec<T> {
+	fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+		let mut vec = Self::new();
+		vec.extend(iter);
+		vec
+	}
+}
+
+#[test]
+fn test() {
+	use std::sync::Arc;
+
+	assert_eq!((locate(0), locate(1), locate(2), locate(6), locate(7)), ((0, 0), (1, 0), (1, 1), (2, 3), (3, 0)));
+	assert_eq!(locate(usize::MAX - 1), (SEGMENTS - 1, (1 << (SEGMENTS - 1)) - 1));
+
+	let vec = SegVec::new();
+	let (_, first) = vec.push(String::from("first"));
+	for i in 0..100 { vec.push(i.to_string()); }
+	assert_eq!((&first[..], &vec[1][..], vec.len(), vec.get(101)), ("first", "0", 101, None));
+	assert_eq!(vec.iter().nth(100).unwrap(), "99");
+	assert_eq!((vec.get(usize::MAX), vec.get(128)), (None, None));
+
+	let arc = Arc::new(());
+	let vec = SegVec::new();
+	std::thread::scope(|scope| {
+		for thread in 0..4 {
+			let (vec, arc) = (&vec, &arc);
+			scope.spawn(move || {
+				for i in 0..500 {
+					let (index, value) = vec.push((thread, i, arc.clone()));
+					assert_eq!((value.0, value.1), (thread, i));
+					assert_eq!(vec[index].1, i);
+					vec.iter().for_each(|value| assert!(value.1 < 500));
+				}
+			});
+		}
+	});
+
+	assert_eq!((vec.len(), vec.iter().count(), Arc::strong_count(&arc)), (2000, 2000, 2001));
+	for thread in 0..4 {
+		let mut seen = vec.iter().filter(|value| value.0 == thread).map(|value| value.1);
+		assert!((0..500).eq(&mut seen));
+	}
+	drop(vec);
+	assert_eq!(Arc::strong_count(&arc), 1);
+}
@@ -1,4 +1,11 @@
-use serde::{*, ser::SerializeSeq};
+use {
+	serde::{
+		*,
+		ser::{SerializeSeq, SerializeMap, Error as _},
+		de::{DeserializeSeed, Visitor, SeqAccess, MapAccess},
+	},
+	std::marker::PhantomData,
+};
 
 pub struct SerIter<I>(std::cell::Cell<Option<I>>);
 
@@ -34,16 +41,136 @@ impl<I, T> Serialize for SerTrustedLen<I> where
 	T: Serialize,
 {
 	fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
-		let mut seq;
-		if let Some(iter) = self.0.take() {
-			seq = ser.serialize_seq(Some(iter.size_hint().1.unwrap()))?;
-			for element in iter {
-				seq.serialize_element(&element)?;
-			}
-		} else {
-			seq = ser.serialize_seq(Some(0))?;
+		let opt_iter = self.0.take();
+
+		let len = match &opt_iter {
+			Some(iter) => iter.size_hint().1.ok_or_else(|| S::Error::custom(
+				"SerTrustedLen iterator has no upper bound"
+			))?,
+			None => 0,
+		};
+
+		let mut seq = ser.serialize_seq(Some(len))?;
+		let mut count = 0;
+
+		for element in opt_iter.into_iter().flatten() {
+			count += 1;
+			if count > len { break; }
+			seq.serialize_element(&element)?;
 		}
+
+		if count != len {
+			return Err(S::Error::custom(format_args!(
+				"SerTrustedLen iterator yielded {} than the {} elements it announced",
+				if count > len { "more" } else { "fewer" }, len,
+			)));
+		}
+
 		seq.end()
 	}
 }
 
+pub struct SerMap<I>(std::cell::Cell<Option<I>>);
+
+impl<I> SerMap<I> {
+	pub fn new(iter: I) -> Self { Self(Some(iter).into()) }
+	pub fn into_inner(self) -> Option<I> { self.0.into_inner() }
+}
+
+impl<I, K, V> Serialize for SerMap<I> where
+	I: Iterator<Item = (K, V)>,
+	K: Serialize,
+	V: Serialize,
+{
+	fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+		let opt_iter = self.0.take();
+
+		let opt_len = match &opt_iter {
+			Some(iter) => match iter.size_hint() {
+				(lower, Some(upper)) if lower == upper => Some(upper),
+				_ => None,
+			},
+			None => Some(0),
+		};
+
+		let mut map = ser.serialize_map(opt_len)?;
+		for (key, value) in opt_iter.into_iter().flatten() {
+			map.serialize_entry(&key, &value)?;
+		}
+		map.end()
+	}
+}
+
+pub struct DeEach<T, F>(F, PhantomData<T>);
+
+impl<T, F> DeEach<T, F> {
+	pub fn new(f: F) -> Self { Self(f, PhantomData) }
+	pub fn into_inner(self) -> F { self.0 }
+}
+
+impl<'de, T, F> DeserializeSeed<'de> for DeEach<T, F> where
+	T: Deserialize<'de>,
+	F: FnMut(T),
+{
+	type Value = ();
+
+	fn deserialize<D: Deserializer<'de>>(self, de: D) -> Result<(), D::Error> {
+		de.deserialize_seq(self)
+	}
+}
+
+impl<'de, T, F> Visitor<'de> for DeEach<T, F> where
+	T: Deserialize<'de>,
+	F: FnMut(T),
+{
+	type Value = ();
+
+	fn expecting(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+		fmt.write_str("a sequence")
+	}
+
+	fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<(), A::Error> {
+		while let Some(element) = seq.next_element()? {
+			(self.0)(element);
+		}
+		Ok(())
+	}
+}
+
+pub struct DeEachEntry<K, V, F>(F, PhantomData<(K, V)>);
+
+impl<K, V, F> DeEachEntry<K, V, F> {
+	pub fn new(f: F) -> Self { Self(f, PhantomData) }
+	pub fn into_inner(self) -> F { self.0 }
+}
+
+impl<'de, K, V, F> DeserializeSeed<'de> for DeEachEntry<K, V, F> where
+	K: Deserialize<'de>,
+	V: Deserialize<'de>,
+	F: FnMut(K, V),
+{
+	type Value = ();
+
+	fn deserialize<D: Deserializer<'de>>(self, de: D) -> Result<(), D::Error> {
+		de.deserialize_map(self)
+	}
+}
+
+impl<'de, K, V, F> Visitor<'de> for DeEachEntry<K, V, F> where
+	K: Deserialize<'de>,
+	V: Deserialize<'de>,
+	F: FnMut(K, V),
+{
+	type Value = ();
+
+	fn expecting(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+		fmt.write_str("a map")
+	}
+
+	fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<(), A::Error> {
+		while let Some((key, value)) = map.next_entry()? {
+			(self.0)(key, value);
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,401 @@
+use {
+	std::{
+		ptr,
+		iter::TrustedLen,
+		mem::{MaybeUninit, take},
+		slice,
+		ops,
+	},
+	crate::{extend_ext::ExtendExt, cvec::CVec},
+};
+
+// keeps up to `N` elements inline, and moves them to a `CVec` once there are
+// more; it doesn't move back when shrinking
+pub struct SmallCVec<T, const N: usize>(Repr<T, N>);
+
+enum Repr<T, const N: usize> {
+	Inline(usize, [MaybeUninit<T>; N]),
+	Heap(CVec<T>),
+}
+
+impl<T, const N: usize> SmallCVec<T, N> {
+	pub fn new() -> Self {
+		Self(Repr::Inline(0, unsafe { MaybeUninit::uninit().assume_init() }))
+	}
+
+	pub fn is_spilled(&self) -> bool { matches!(self.0, Repr::Heap(_)) }
+
+	pub fn as_ptr(&self) -> *const T {
+		match &self.0 {
+			Repr::Inline(_, buf) => buf.as_ptr() as _,
+			Repr::Heap(vec) => vec.as_ptr(),
+		}
+	}
+
+	pub fn as_mut_ptr(&mut self) -> *mut T {
+		match &mut self.0 {
+			Repr::Inline(_, buf) => buf.as_mut_ptr() as _,
+			Repr::Heap(vec) => vec.as_ptr(),
+		}
+	}
+
+	pub fn into_cvec(mut self) -> CVec<T> {
+		self.spill();
+		match &mut self.0 {
+			Repr::Heap(vec) => take(vec),
+			Repr::Inline(..) => unreachable!(),
+		}
+	}
+
+	fn spill(&mut self) {
+		if let Repr::Inline(len, buf) = &mut self.0 {
+			let (len, buf) = (take(len), buf.as_ptr());
+			let mut vec = CVec::new();
+			vec.extend_trusted_len((0..len).map(|i| unsafe { ptr::read(buf.add(i) as *const T) }));
+			self.0 = Repr::Heap(vec);
+		}
+	}
+
+	pub fn reserve(&mut self, additional: usize) {
+		if self.len() + additional > N {
+			self.spill();
+		}
+	}
+
+	pub fn push(&mut self, elem: T) {
+		match &mut self.0 {
+			Repr::Inline(len, buf) if *len < N => {
+				buf[*len] = MaybeUninit::new(elem);
+				*len += 1;
+			}
+			Repr::Inline(..) => {
+				self.spill();
+				self.push(elem);
+			}
+			Repr::Heap(vec) => vec.push(elem),
+		}
+	}
+
+	pub fn pop(&mut self) -> Option<T> {
+		match &mut self.0 {
+			Repr::Inline(len, buf) => {
+				*len = len.checked_sub(1)?;
+				Some(unsafe { ptr::read(buf[*len].as_ptr()) })
+			}
+			Repr::Heap(vec) => vec.pop(),
+		}
+	}
+
+	pub fn truncate(&mut self, to: usize) {
+		match &mut self.0 {
+			Repr::Inline(len, buf) if to < *len => unsafe {
+				let tail = slice::from_raw_parts_mut(buf.as_mut_ptr().add(to) as *mut T, *len - to);
+				*len = to;
+				ptr::drop_in_place(tail);
+			}
+			Repr::Inline(..) => {}
+			Repr::Heap(vec) => vec.truncate(to),
+		}
+	}
+
+	pub fn clear(&mut self) { self.truncate(0); }
+
+	// moves `count` elements from `data` to the end
+	unsafe fn extend_move(&mut self, data: *const T, count: usize) {
+		self.reserve(count);
+		match &mut self.0 {
+			Repr::Inline(len, buf) => {
+				ptr::copy_nonoverlapping(data, buf.as_mut_ptr().add(*len) as *mut T, count);
+				*len += count;
+			}
+			Repr::Heap(vec) => vec.extend_trusted_len((0..count).map(|i| ptr::read(data.add(i)))),
+		}
+	}
+
+	// makes the vector empty without dropping its elements
+	unsafe fn forget_elems(&mut self) {
+		match &mut self.0 {
+			Repr::Inline(len, _) => *len = 0,
+			Repr::Heap(vec) => vec.clear_forget(),
+		}
+	}
+}
+
+impl<T, const N: usize> Drop for SmallCVec<T, N> {
+	fn drop(&mut self) {
+		if let Repr::Inline(..) = self.0 {
+			self.clear();
+		}
+	}
+}
+
+impl<T, const N: usize> Default for SmallCVec<T, N> {
+	fn default() -> Self { Self::new() }
+}
+
+impl<T, const N: usize> ops::Deref for SmallCVec<T, N> {
+	type Target = [T];
+	fn deref(&self) -> &[T] {
+		match &self.0 {
+			Repr::Inline(len, buf) => unsafe { slice::from_raw_parts(buf.as_ptr() as _, *len) },
+			Repr::Heap(vec) => vec,
+		}
+	}
+}
+
+impl<T, const N: usize> ops::DerefMut for SmallCVec<T, N> {
+	fn deref_mut(&mut self) -> &mut [T] {
+		match &mut self.0 {
+			Repr::Inline(len, buf) => unsafe { slice::from_raw_parts_mut(buf.as_mut_ptr() as _, *len) },
+			Repr::Heap(vec) => vec,
+		}
+	}
+}
+
+impl<T: Clone, const N: usize> Clone for SmallCVec<T, N> {
+	fn clone(&self) -> Self {
+		let mut vec = Self::new();
+		vec.extend_trusted_len(self.iter().cloned());
+		vec
+	}
+}
+
+impl<T: std::fmt::Debug, const N: usize> std::fmt::Debug for SmallCVec<T, N> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		(**self).fmt(f)
+	}
+}
+
+impl<T: PartialEq, const N: usize> PartialEq for SmallCVec<T, N> {
+	fn eq(&self, rhs: &Self) -> bool { **self == **rhs }
+}
+
+impl<T: Eq, const N: usize> Eq for SmallCVec<T, N> {}
+
+impl<T: std::hash::Hash, const N: usize> std::hash::Hash for SmallCVec<T, N> {
+	fn hash<H: std::hash::Hasher>(&self, h: &mut H) {
+		(**self).hash(h);
+	}
+}
+
+impl<T: PartialOrd, const N: usize> PartialOrd for SmallCVec<T, N> {
+	fn partial_cmp(&self, rhs: &Self) -> Option<std::cmp::Ordering> {
+		(**self).partial_cmp(&**rhs)
+	}
+}
+
+impl<T: Ord, const N: usize> Ord for SmallCVec<T, N> {
+	fn cmp(&self, rhs: &Self) -> std::cmp::Ordering {
+		(**self).cmp(&**rhs)
+	}
+}
+
+impl<T, const N: usize> From<CVec<T>> for SmallCVec<T, N> {
+	fn from(vec: CVec<T>) -> Self { Self(Repr::Heap(vec)) }
+}
+
+impl<T, const N: usize> Extend<T> for SmallCVec<T, N> {
+	fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+		let iter = iter.into_iter();
+		self.reserve(iter.size_hint().0);
+		match &mut self.0 {
+			Repr::Heap(vec) => vec.extend(iter),
+			Repr::Inline(..) => iter.for_each(|elem| self.push(elem)),
+		}
+	}
+
+	fn extend_one(&mut self, elem: T) { self.push(elem); }
+}
+
+impl<T, const N: usize> std::iter::FromIterator<T> for SmallCVec<T, N> {
+	fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+		let mut vec = Self::new();
+		vec.extend(iter);
+		vec
+	}
+}
+
+impl<T, const N: usize> ExtendExt<T> for SmallCVec<T, N> {
+	fn extend_copy_slice(&mut self, s: &[T]) where T: Copy {
+		unsafe { self.extend_move(s.as_ptr(), s.len()); }
+	}
+
+	fn extend_trusted_len(&mut self, elems: impl TrustedLen<Item = T>) {
+		self.reserve(elems.size_hint().1.unwrap());
+		match &mut self.0 {
+			Repr::Heap(vec) => vec.extend_trusted_len(elems),
+			Repr::Inline(..) => elems.for_each(|elem| self.push(elem)),
+		}
+	}
+
+	fn extend_append_self(&mut self, rhs: &mut Self) {
+		if self.is_empty() {
+			std::mem::swap(self, rhs);
+		} else {
+			unsafe {
+				self.extend_move(rhs.as_ptr(), rhs.len());
+				rhs.forget_elems();
+			}
+		}
+	}
+
+	fn extend_append_vec(&mut self, rhs: &mut Vec<T>) {
+		if self.is_empty() && rhs.len() > N {
+			*self = CVec::from(take(rhs)).into();
+		} else {
+			unsafe {
+				self.extend_move(rhs.as_ptr(), rhs.len());
+				rhs.set_len(0);
+			}
+		}
+	}
+
+	fn extend_append_cvec(&mut self, rhs: &mut CVec<T>) {
+		if self.is_empty() && rhs.len() > N {
+			*self = take(rhs).into();
+		} else {
+			unsafe {
+				self.extend_move(rhs.as_ptr(), rhs.len());
+				rhs.clear_forget();
+			}
+		}
+	}
+}
+
+unsafe impl<T, const N: usize> crate::vec_ext::VecExt<T> for SmallCVec<T, N> {
+	fn clear(&mut self) { SmallCVec::clear(self); }
+	fn pop(&mut self) -> Option<T> { SmallCVec::pop(self) }
+}
+
+pub struct IntoIter<T, const N: usize> { vec: SmallCVec<T, N>, pos: usize }
+
+impl<T, const N: usize> IntoIterator for SmallCVec<T, N> {
+	type IntoIter = IntoIter<T, N>;
+	type Item = T;
+	fn into_iter(self) -> IntoIter<T, N> { IntoIter { vec: self, pos: 0 } }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a SmallCVec<T, N> {
+	type IntoIter = slice::Iter<'a, T>;
+	type Item = &'a T;
+	fn into_iter(self) -> Self::IntoIter { (**self).iter() }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a mut SmallCVec<T, N> {
+	type IntoIter = slice::IterMut<'a, T>;
+	type Item = &'a mut T;
+	fn into_iter(self) -> Self::IntoIter { (**self).iter_mut() }
+}
+
+impl<T, const N: usize> Iterator for IntoIter<T, N> {
+	type Item = T;
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		let len = self.len();
+		(len, Some(len))
+	}
+
+	fn next(&mut self) -> Option<T> {
+		if self.pos == self.vec.len() { return None; }
+		self.pos += 1;
+		Some(unsafe { ptr::read(self.vec.as_ptr().add(self.pos - 1)) })
+	}
+}
+
+impl<T, const N: usize> ExactSizeIterator for IntoIter<T, N> {
+	fn len(&self) -> usize { self.vec.len() - self.pos }
+}
+
+unsafe impl<T, const N: usize> TrustedLen for IntoIter<T, N> {}
+
+impl<T, const N: usize> Drop for IntoIter<T, N> {
+	fn drop(&mut self) {
+		let remaining = unsafe {
+			ptr::slice_from_raw_parts_mut(self.vec.as_mut_ptr().add(self.pos), self.len())
+		};
+		// the buffer is freed even if dropping an element panics
+		let vec = crate::on_drop::OnDrop::new(&mut self.vec, |vec| unsafe { vec.forget_elems() });
+		unsafe { ptr::drop_in_place(remaining); }
+		drop(vec);
+	}
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize, const N: usize> serde::Serialize for SmallCVec<T, N> {
+	fn serialize<S: serde::Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+		ser.collect_seq(&**self)
+	}
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>, const N: usize> serde::Deserialize<'de> for SmallCVec<T, N> {
+	fn deserialize<D: serde::Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+		struct Visitor<T, const N: usize>(std::marker::PhantomData<T>);
+
+		impl<'de, T: serde::Deserialize<'de>, const N: usize> serde::de::Visitor<'de> for Visitor<T, N> {
+			type Value = SmallCVec<T, N>;
+
+			fn expecting(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+				fmt.write_str("SmallCVec sequence")
+			}
+
+			fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+				let mut vec = SmallCVec::new();
+				while let Some(elem) = seq.next_element()? {
+					vec.push(elem);
+				}
+				Ok(vec)
+			}
+		}
+
+		de.deserialize_seq(Visitor::<T, N>(std::marker::PhantomData))
+	}
+}
+
+#[test]
+fn test() {
+	use std::rc::Rc;
+
+	let mut vec = SmallCVec::<u32, 4>::new();
+	vec.extend(0..3);
+	vec.extend_copy_slice(&[3]);
+	assert!(!vec.is_spilled());
+	vec.push(4);
+	assert!(vec.is_spilled());
+	assert_eq!(vec[..], [0, 1, 2, 3, 4]);
+	vec.truncate(1);
+	assert_eq!((vec.pop(), vec.pop()), (Some(0), None));
+
+	let mut cvec = CVec::from_copy(&[1, 2, 3, 4, 5]);
+	let data = cvec.as_ptr();
+	let mut vec = SmallCVec::<u32, 4>::new();
+	vec.extend_append_cvec(&mut cvec);
+	assert_eq!((vec.as_ptr(), cvec.len()), (data as *const _, 0));
+	let mut small = [6, 7].iter().copied().collect::<SmallCVec<u32, 4>>();
+	vec.extend_append_self(&mut small);
+	assert_eq!((&vec.into_cvec()[..], small.len()), (&[1, 2, 3, 4, 5, 6, 7][..], 0));
+
+	let rc = Rc::new(());
+	let mut vec = SmallCVec::<_, 3>::new();
+	vec.extend(std::iter::repeat(rc.clone()).take(2));
+	let mut iter = vec.clone().into_iter();
+	iter.next();
+	assert_eq!(Rc::strong_count(&rc), 4);
+	drop((vec, iter));
+	assert_eq!(Rc::strong_count(&rc), 1);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn bytes_buffer() {
+	use crate::bytes::BytesSer;
+
+	let value = (vec![1u64, 2, 3], "string", Some(()));
+
+	let mut small: BytesSer<SmallCVec<u8, 64>, SmallCVec<_, 1>> = BytesSer::new();
+	small.serialize(&value).unwrap();
+	let mut ser: BytesSer = BytesSer::new();
+	ser.serialize(&value).unwrap();
+	assert_eq!(small.bytes().collect::<Vec<_>>(), ser.bytes().collect::<Vec<_>>());
+	assert!(!small.into_inner().0.is_spilled());
+}
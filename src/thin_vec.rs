@@ -0,0 +1,445 @@
+use {
+	std::{
+		ptr::{self, NonNull},
+		iter::TrustedLen,
+		marker::PhantomData,
+		mem::replace,
+		slice,
+		ops,
+		alloc::{Layout, Global, Allocator},
+	},
+	crate::{extend_ext::ExtendExt, cvec::CVec},
+};
+
+#[repr(C)]
+struct Header {
+	len: usize,
+	cap: usize,
+}
+
+// every empty `ThinVec` points here, it's never written to
+static EMPTY: Header = Header { len: 0, cap: 0 };
+
+// a vector that is a single pointer, to a header holding the length and capacity
+// followed by the elements; empty vectors don't allocate
+pub struct ThinVec<T> {
+	header: NonNull<Header>,
+	_elems: PhantomData<T>,
+}
+
+impl<T> ThinVec<T> {
+	pub fn new() -> Self {
+		Self { header: NonNull::from(&EMPTY), _elems: PhantomData }
+	}
+
+	pub fn with_capacity(cap: usize) -> Self {
+		let mut vec = Self::new();
+		vec.reserve(cap);
+		vec
+	}
+
+	fn layout(cap: usize) -> (Layout, usize) {
+		let (layout, offset) = Layout::new::<Header>()
+			.extend(Layout::array::<T>(cap).unwrap()).unwrap();
+		(layout.pad_to_align(), offset)
+	}
+
+	fn header(&self) -> &Header { unsafe { self.header.as_ref() } }
+
+	fn is_static(&self) -> bool { self.header() as *const Header == &EMPTY }
+
+	pub fn len(&self) -> usize { self.header().len }
+
+	pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+	pub fn cap(&self) -> usize { self.header().cap }
+
+	pub fn as_ptr(&self) -> *mut T {
+		if self.is_static() {
+			// the static header isn't aligned for every `T`
+			NonNull::dangling().as_ptr()
+		} else {
+			unsafe { (self.header.as_ptr() as *mut u8).add(Self::layout(0).1) as *mut T }
+		}
+	}
+
+	// only for allocated vectors
+	unsafe fn set_len(&mut self, len: usize) {
+		debug_assert!(len <= self.cap());
+		self.header.as_mut().len = len;
+	}
+
+	pub fn reserve(&mut self, additional: usize) {
+		let (len, cap) = (self.len(), self.cap());
+		let need = len.checked_add(additional).expect("capacity overflow");
+		if need <= cap { return; }
+
+		let new_cap = need.max(cap * 2).max(4);
+		let new_layout = Self::layout(new_cap).0;
+
+		self.header = unsafe {
+			let header = if self.is_static() {
+				Global.allocate(new_layout)
+			} else {
+				Global.grow(self.header.cast(), Self::layout(cap).0, new_layout)
+			};
+			let mut header = header.unwrap().cast::<Header>();
+			header.as_mut().cap = new_cap;
+			header.as_mut().len = len;
+			header
+		};
+	}
+
+	pub fn push(&mut self, elem: T) {
+		let len = self.len();
+		self.reserve(1);
+		unsafe {
+			ptr::write(self.as_ptr().add(len), elem);
+			self.set_len(len + 1);
+		}
+	}
+
+	pub fn pop(&mut self) -> Option<T> {
+		let len = self.len().checked_sub(1)?;
+		unsafe {
+			self.set_len(len);
+			Some(ptr::read(self.as_ptr().add(len)))
+		}
+	}
+
+	pub fn truncate(&mut self, len: usize) {
+		let old_len = self.len();
+		if len < old_len {
+			unsafe {
+				self.set_len(len);
+				ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.as_ptr().add(len), old_len - len));
+			}
+		}
+	}
+
+	pub fn clear(&mut self) { self.truncate(0); }
+
+	// frees the allocation, going back to the shared empty header
+	pub fn shrink_to_empty(&mut self) {
+		self.clear();
+		if !self.is_static() {
+			let cap = self.cap();
+			let header = replace(&mut self.header, NonNull::from(&EMPTY));
+			unsafe { Global.deallocate(header.cast(), Self::layout(cap).0); }
+		}
+	}
+
+	unsafe fn extend_copy_unsafe(&mut self, data: *const T, count: usize) {
+		let len = self.len();
+		self.reserve(count);
+		ptr::copy_nonoverlapping(data, self.as_ptr().add(len), count);
+		if count > 0 { self.set_len(len + count); }
+	}
+}
+
+impl<T> Drop for ThinVec<T> {
+	fn drop(&mut self) {
+		if !self.is_static() {
+			unsafe {
+				ptr::drop_in_place(&mut **self as *mut [T]);
+				Global.deallocate(self.header.cast(), Self::layout(self.cap()).0);
+			}
+		}
+	}
+}
+
+impl<T> Default for ThinVec<T> {
+	fn default() -> Self { Self::new() }
+}
+
+unsafe impl<T: Send> Send for ThinVec<T> {}
+unsafe impl<T: Sync> Sync for ThinVec<T> {}
+
+impl<T> ops::Deref for ThinVec<T> {
+	type Target = [T];
+	fn deref(&self) -> &[T] {
+		unsafe { slice::from_raw_parts(self.as_ptr(), self.len()) }
+	}
+}
+
+impl<T> ops::DerefMut for ThinVec<T> {
+	fn deref_mut(&mut self) -> &mut [T] {
+		unsafe { slice::from_raw_parts_mut(self.as_ptr(), self.len()) }
+	}
+}
+
+impl<T: Clone> Clone for ThinVec<T> {
+	fn clone(&self) -> Self {
+		let mut vec = Self::with_capacity(self.len());
+		vec.extend(self.iter().cloned());
+		vec
+	}
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for ThinVec<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		(**self).fmt(f)
+	}
+}
+
+impl<T: PartialEq> PartialEq for ThinVec<T> {
+	fn eq(&self, rhs: &Self) -> bool { **self == **rhs }
+}
+
+impl<T: Eq> Eq for ThinVec<T> {}
+
+impl<T: std::hash::Hash> std::hash::Hash for ThinVec<T> {
+	fn hash<H: std::hash::Hasher>(&self, h: &mut H) {
+		(**self).hash(h);
+	}
+}
+
+impl<T: PartialOrd> PartialOrd for ThinVec<T> {
+	fn partial_cmp(&self, rhs: &Self) -> Option<std::cmp::Ordering> {
+		(**self).partial_cmp(&**rhs)
+	}
+}
+
+impl<T: Ord> Ord for ThinVec<T> {
+	fn cmp(&self, rhs: &Self) -> std::cmp::Ordering {
+		(**self).cmp(&**rhs)
+	}
+}
+
+impl<T> Extend<T> for ThinVec<T> {
+	fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+		let iter = iter.into_iter();
+		self.reserve(iter.size_hint().0);
+		iter.for_each(|elem| self.push(elem));
+	}
+
+	fn extend_one(&mut self, elem: T) { self.push(elem); }
+}
+
+impl<T> std::iter::FromIterator<T> for ThinVec<T> {
+	fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+		let mut vec = Self::new();
+		vec.extend(iter);
+		vec
+	}
+}
+
+impl<T> ExtendExt<T> for ThinVec<T> {
+	fn extend_copy_slice(&mut self, s: &[T]) where T: Copy {
+		unsafe { self.extend_copy_unsafe(s.as_ptr(), s.len()); }
+	}
+
+	fn extend_trusted_len(&mut self, elems: impl TrustedLen<Item = T>) {
+		self.reserve(elems.size_hint().1.unwrap());
+		elems.for_each(|elem| self.push(elem));
+	}
+
+	fn extend_append_self(&mut self, rhs: &mut Self) {
+		if self.is_empty() {
+			std::mem::swap(self, rhs);
+		} else {
+			unsafe {
+				self.extend_copy_unsafe(rhs.as_ptr(), rhs.len());
+				if !rhs.is_empty() { rhs.set_len(0); }
+			}
+		}
+	}
+
+	fn extend_append_vec(&mut self, rhs: &mut Vec<T>) {
+		unsafe {
+			self.extend_copy_unsafe(rhs.as_ptr(), rhs.len());
+			rhs.set_len(0);
+		}
+	}
+
+	fn extend_append_cvec(&mut self, rhs: &mut CVec<T>) {
+		unsafe { self.extend_copy_unsafe(rhs.as_ptr(), rhs.len()); }
+		rhs.clear_forget();
+	}
+}
+
+unsafe impl<T> crate::vec_ext::VecExt<T> for ThinVec<T> {
+	fn clear(&mut self) { ThinVec::clear(self); }
+	fn pop(&mut self) -> Option<T> { ThinVec::pop(self) }
+}
+
+pub struct IntoIter<T> { vec: ThinVec<T>, pos: usize }
+
+impl<T> IntoIterator for ThinVec<T> {
+	type IntoIter = IntoIter<T>;
+	type Item = T;
+	fn into_iter(self) -> IntoIter<T> { IntoIter { vec: self, pos: 0 } }
+}
+
+impl<'a, T> IntoIterator for &'a ThinVec<T> {
+	type IntoIter = slice::Iter<'a, T>;
+	type Item = &'a T;
+	fn into_iter(self) -> Self::IntoIter { (**self).iter() }
+}
+
+impl<'a, T> IntoIterator for &'a mut ThinVec<T> {
+	type IntoIter = slice::IterMut<'a, T>;
+	type Item = &'a mut T;
+	fn into_iter(self) -> Self::IntoIter { (**self).iter_mut() }
+}
+
+impl<T> Iterator for IntoIter<T> {
+	type Item = T;
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		let len = self.len();
+		(len, Some(len))
+	}
+
+	fn next(&mut self) -> Option<T> {
+		if self.pos == self.vec.len() { return None; }
+		self.pos += 1;
+		Some(unsafe { ptr::read(self.vec.as_ptr().add(self.pos - 1)) })
+	}
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {
+	fn len(&self) -> usize { self.vec.len() - self.pos }
+}
+
+unsafe impl<T> TrustedLen for IntoIter<T> {}
+
+impl<T> Drop for IntoIter<T> {
+	fn drop(&mut self) {
+		let (pos, len) = (self.pos, self.vec.len());
+		if len == 0 { return; }
+		unsafe {
+			// the vector forgets its elements first, so a panicking drop leaks the rest
+			self.vec.set_len(0);
+			ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.vec.as_ptr().add(pos), len - pos));
+		}
+	}
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for ThinVec<T> {
+	fn serialize<S: serde::Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+		ser.collect_seq(&**self)
+	}
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for ThinVec<T> {
+	fn deserialize<D: serde::Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+		struct Visitor<T>(PhantomData<T>);
+
+		impl<'de, T: serde::Deserialize<'de>> serde::de::Visitor<'de> for Visitor<T> {
+			type Value = ThinVec<T>;
+
+			fn expecting(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+				fmt.write_str("ThinVec sequence")
+			}
+
+			fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<ThinVec<T>, A::Error> {
+				let mut vec = ThinVec::with_capacity(seq.size_hint().unwrap_or(0).min(1 << 12));
+				while let Some(elem) = seq.next_element()? {
+					vec.push(elem);
+				}
+				Ok(vec)
+			}
+		}
+
+		de.deserialize_seq(Visitor(PhantomData))
+	}
+}
+
+#[test]
+fn test() {
+	use std::{rc::Rc, mem::{align_of, size_of}};
+
+	assert_eq!(size_of::<ThinVec<u64>>(), size_of::<usize>());
+	assert_eq!(size_of::<Option<ThinVec<u64>>>(), size_of::<usize>());
+
+	let (a, b) = (ThinVec::<u8>::new(), ThinVec::<[u128; 3]>::new());
+	assert_eq!(a.header, b.header);
+	assert_eq!(b.as_ptr() as usize % align_of::<[u128; 3]>(), 0);
+
+	let mut vec = ThinVec::new();
+	for i in 0..100u16 { vec.push(i); }
+	assert_eq!((vec.len(), vec.iter().map(|&i| i as u32).sum::<u32>()), (100, 4950));
+	assert_eq!(vec.pop(), Some(99));
+	vec.truncate(3);
+	vec.extend_copy_slice(&[7, 8]);
+	assert_eq!(vec[..], [0, 1, 2, 7, 8]);
+
+	let mut cvec = CVec::from_copy(&[9]);
+	vec.extend_append_cvec(&mut cvec);
+	let mut other = vec.clone();
+	vec.extend_append_self(&mut other);
+	assert_eq!((vec.len(), other.len(), cvec.len()), (12, 0, 0));
+	vec.shrink_to_empty();
+	assert!(vec.is_static());
+
+	let live = || counting::LIVE.with(|live| live.get());
+	let before = live();
+	let mut vec = (0..100u64).collect::<ThinVec<_>>();
+	vec.shrink_to_empty();
+	drop((0..100u64).collect::<ThinVec<_>>());
+	assert_eq!(live(), before);
+
+	let rc = Rc::new(());
+	let mut vec = (0..5).map(|_| rc.clone()).collect::<ThinVec<_>>();
+	vec.extend_trusted_len(std::iter::repeat(rc.clone()).take(3));
+	let mut iter = vec.into_iter();
+	iter.next();
+	assert_eq!((iter.len(), Rc::strong_count(&rc)), (7, 8));
+	drop(iter);
+	assert_eq!(Rc::strong_count(&rc), 1);
+
+	let mut units = ThinVec::new();
+	units.extend(std::iter::repeat(()).take(1000));
+	assert_eq!(units.len(), 1000);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde() {
+	use crate::bytes::{BytesSer, BytesDe};
+
+	let vec = (0..300u32).collect::<ThinVec<_>>();
+	let mut ser: BytesSer = BytesSer::new();
+	ser.serialize(&vec).unwrap();
+	ser.serialize(&ThinVec::<String>::new()).unwrap();
+	let data = ser.bytes().collect::<Vec<u8>>();
+
+	let mut ser: BytesSer = BytesSer::new();
+	ser.serialize(&(0..300u32).collect::<Vec<_>>()).unwrap();
+	ser.serialize(&Vec::<String>::new()).unwrap();
+	assert_eq!(data, ser.bytes().collect::<Vec<u8>>());
+
+	let mut slice = &data[..];
+	let mut de = BytesDe::new(&mut slice);
+	assert_eq!(de.deserialize::<ThinVec<u32>>().unwrap(), vec);
+	assert!(de.deserialize::<ThinVec<String>>().unwrap().is_static());
+}
+
+// counts the bytes each thread has allocated and not freed, by the layouts it
+// passes in, so that freeing with the wrong layout shows up
+#[cfg(test)]
+mod counting {
+	use std::{alloc::{GlobalAlloc, Layout, System}, cell::Cell};
+
+	thread_local!(pub static LIVE: Cell<isize> = const { Cell::new(0) });
+
+	struct Counting;
+
+	unsafe impl GlobalAlloc for Counting {
+		unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+			let _ = LIVE.try_with(|live| live.set(live.get() + layout.size() as isize));
+			System.alloc(layout)
+		}
+
+		unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+			let _ = LIVE.try_with(|live| live.set(live.get() - layout.size() as isize));
+			System.dealloc(ptr, layout)
+		}
+	}
+
+	#[global_allocator]
+	static GLOBAL: Counting = Counting;
+}